@@ -1,7 +1,8 @@
 use mrauth::auth_proto::AuthorizationFailureResponse;
-use reqwest::Client;
-use proto::GetAccessTokenResponse;
+use reqwest::{Client, Response};
+use proto::{CreateLoginTicketRequest, CreateLoginTicketResponse, GetAccessTokenResponse, GetMeResponse, GetStatusResponse, ListConnectionsResponse};
 use reqwest_protobuf::{ProtobufRequestExt, ProtobufResponseExt};
+use serde::Serialize;
 
 mod error;
 pub use error::*;
@@ -15,17 +16,117 @@ pub struct ExactAuthClient {
 pub struct AccessToken {
     pub token: String,
     pub expires_at: i64,
+    /// The connection the token belongs to
+    pub connection_id: String,
+    /// The Exact scopes requested when the user last logged in. Empty if unknown
+    pub scopes: Vec<String>,
 }
 
 impl From<GetAccessTokenResponse> for AccessToken {
     fn from(x: GetAccessTokenResponse) -> Self {
         Self {
             token: x.token,
-            expires_at: x.expires_at
+            expires_at: x.expires_at,
+            connection_id: x.connection_id,
+            scopes: x.scopes,
         }
     }
 }
 
+/// A connection to a single Exact Online account
+pub struct Connection {
+    pub id: String,
+    pub label: Option<String>,
+    pub region: String,
+    pub reauthorization_required: bool,
+    /// The owner the connection is shared with, `None` for a personal connection
+    pub owner_id: Option<String>,
+}
+
+impl From<proto::Connection> for Connection {
+    fn from(x: proto::Connection) -> Self {
+        Self {
+            id: x.id,
+            label: x.label,
+            region: x.region,
+            reauthorization_required: x.reauthorization_required,
+            owner_id: x.owner_id,
+        }
+    }
+}
+
+pub struct ExactUser {
+    pub exact_user_id: String,
+    pub full_name: String,
+    pub email: String,
+    pub current_division: i64,
+}
+
+impl From<GetMeResponse> for ExactUser {
+    fn from(x: GetMeResponse) -> Self {
+        Self {
+            exact_user_id: x.exact_user_id,
+            full_name: x.full_name,
+            email: x.email,
+            current_division: x.current_division,
+        }
+    }
+}
+
+pub struct ConnectionStatus {
+    /// Whether the user has the connection. If not, no other field is set
+    pub connected: bool,
+    pub connection_id: Option<String>,
+    pub access_token_expires_at: Option<i64>,
+    pub refresh_token_expires_at: Option<i64>,
+    pub last_refreshed_at: Option<i64>,
+    pub reauthorization_required: bool,
+    pub scopes: Vec<String>,
+}
+
+impl From<GetStatusResponse> for ConnectionStatus {
+    fn from(x: GetStatusResponse) -> Self {
+        Self {
+            connected: x.connected,
+            connection_id: x.connection_id,
+            access_token_expires_at: x.access_token_expires_at,
+            refresh_token_expires_at: x.refresh_token_expires_at,
+            last_refreshed_at: x.last_refreshed_at,
+            reauthorization_required: x.reauthorization_required,
+            scopes: x.scopes,
+        }
+    }
+}
+
+pub struct LoginTicket {
+    /// The URL the user's browser should open to start logging in with Exact
+    pub url: String,
+    pub expires_at: i64,
+}
+
+impl From<CreateLoginTicketResponse> for LoginTicket {
+    fn from(x: CreateLoginTicketResponse) -> Self {
+        Self {
+            url: x.url,
+            expires_at: x.expires_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ConnectionQuery<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connection: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct AccessTokenQuery<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connection: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scopes: Option<&'a str>,
+}
+
 impl ExactAuthClient {
     pub fn new(base_url: String, user_agent: &str) -> reqwest::Result<Self> {
         let client = Client::builder()
@@ -41,25 +142,99 @@ impl ExactAuthClient {
         format!("{}{path}", &self.base_url)
     }
 
-    pub async fn get_exact_access_token(&self, mrauth_bearer: &str) -> Result<AccessToken, Error> {
+    /// Get an access token for one of the user's connections.
+    /// `connection_id` may only be omitted if the user has a single connection.
+    /// `required_scopes` are the space separated Exact scopes the token must have,
+    /// if they were not requested when the user last logged in the request fails with `409 Conflict` and the user must log in again.
+    pub async fn get_exact_access_token(&self, mrauth_bearer: &str, connection_id: Option<&str>, required_scopes: Option<&str>) -> Result<AccessToken, Error> {
         let response = self.client
             .get(self.get_url("/api/v1/access-token"))
+            .query(&AccessTokenQuery { connection: connection_id, scopes: required_scopes })
             .bearer_auth(mrauth_bearer)
             .accept_protobuf()
             .send()
             .await?;
 
-        if response.status() == 403 {
-            let payload: AuthorizationFailureResponse = response.protobuf().await?;
-            return Err(Error::Auth(payload));
-        }
+        let payload: GetAccessTokenResponse = decode(response).await?;
+        Ok(payload.into())
+    }
 
-        response.error_for_status_ref()?;
+    /// Get the Exact user of one of the user's connections.
+    /// `connection_id` may only be omitted if the user has a single connection.
+    pub async fn get_exact_user(&self, mrauth_bearer: &str, connection_id: Option<&str>) -> Result<ExactUser, Error> {
+        let response = self.client
+            .get(self.get_url("/api/v1/me"))
+            .query(&ConnectionQuery { connection: connection_id })
+            .bearer_auth(mrauth_bearer)
+            .accept_protobuf()
+            .send()
+            .await?;
 
-        let payload: GetAccessTokenResponse = response.protobuf().await?;
-        Ok(AccessToken {
-            token: payload.token,
-            expires_at: payload.expires_at,
-        })
+        let payload: GetMeResponse = decode(response).await?;
+        Ok(payload.into())
+    }
+
+    /// Get the status of one of the user's connections.
+    /// `connection_id` may only be omitted if the user has a single connection.
+    pub async fn get_status(&self, mrauth_bearer: &str, connection_id: Option<&str>) -> Result<ConnectionStatus, Error> {
+        let response = self.client
+            .get(self.get_url("/api/v1/status"))
+            .query(&ConnectionQuery { connection: connection_id })
+            .bearer_auth(mrauth_bearer)
+            .accept_protobuf()
+            .send()
+            .await?;
+
+        let payload: GetStatusResponse = decode(response).await?;
+        Ok(payload.into())
     }
-}
\ No newline at end of file
+
+    pub async fn list_connections(&self, mrauth_bearer: &str) -> Result<Vec<Connection>, Error> {
+        let response = self.client
+            .get(self.get_url("/api/v1/connections"))
+            .bearer_auth(mrauth_bearer)
+            .accept_protobuf()
+            .send()
+            .await?;
+
+        let payload: ListConnectionsResponse = decode(response).await?;
+        Ok(payload.connections.into_iter()
+            .map(Connection::from)
+            .collect())
+    }
+
+    /// Create a single-use ticket to log the user in with Exact.
+    /// `region` defaults to the Netherlands if not provided.
+    /// `connection_label` selects the connection to log in to, a new connection is created if the user has none with that label.
+    /// `connection_id` instead selects a shared connection to reauthorize, which the user must have logged in to Exact for.
+    pub async fn create_login_ticket(&self, mrauth_bearer: &str, scopes: &str, caller: &str, region: Option<&str>, connection_label: Option<&str>, connection_id: Option<&str>) -> Result<LoginTicket, Error> {
+        let response = self.client
+            .post(self.get_url("/api/v1/login-ticket"))
+            .bearer_auth(mrauth_bearer)
+            .accept_protobuf()
+            .protobuf(CreateLoginTicketRequest {
+                scopes: scopes.to_string(),
+                caller: caller.to_string(),
+                region: region.map(str::to_string),
+                connection_label: connection_label.map(str::to_string),
+                connection_id: connection_id.map(str::to_string),
+            })?
+            .send()
+            .await?;
+
+        let payload: CreateLoginTicketResponse = decode(response).await?;
+        Ok(payload.into())
+    }
+}
+
+/// Decode a protobuf response, or the authorization failure if the request was forbidden
+async fn decode<T: prost::Message + Default>(response: Response) -> Result<T, Error> {
+    if response.status() == 403 {
+        let payload: AuthorizationFailureResponse = response.protobuf().await?;
+        return Err(Error::Auth(payload));
+    }
+
+    response.error_for_status_ref()?;
+
+    Ok(response.protobuf().await?)
+}
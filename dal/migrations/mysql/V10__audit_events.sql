@@ -0,0 +1,17 @@
+-- Actions and outcomes are validated by the application,
+-- so that recording a new kind of action does not require a migration
+CREATE TABLE audit_events (
+    id BIGINT NOT NULL AUTO_INCREMENT,
+    timestamp BIGINT NOT NULL,
+    actor VARCHAR(64) NOT NULL,
+    user_id VARCHAR(32) NULL,
+    action VARCHAR(32) NOT NULL,
+    outcome VARCHAR(16) NOT NULL,
+    detail TEXT NULL,
+    ip VARCHAR(45) NULL,
+    user_agent TEXT NULL,
+    PRIMARY KEY (id),
+    INDEX audit_events_timestamp (timestamp),
+    INDEX audit_events_actor (actor, id),
+    INDEX audit_events_user_id (user_id, id)
+);
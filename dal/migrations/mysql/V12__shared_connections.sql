@@ -0,0 +1,24 @@
+-- Organisations and service identities may own connections, which are shared with the users they are granted to
+CREATE TABLE connection_owners (
+    id VARCHAR(32) NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    kind ENUM('Organisation', 'Service') NOT NULL,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+ALTER TABLE connections
+    ADD COLUMN owner_id VARCHAR(32) NULL,
+    ADD INDEX connections_owner_id (owner_id),
+    ADD FOREIGN KEY (owner_id) REFERENCES connection_owners(id);
+
+-- Grantees are MrAuth users or service accounts, which need not have logged in to ExactAuth themselves
+CREATE TABLE connection_grants (
+    connection_id VARCHAR(32) NOT NULL,
+    grantee_id VARCHAR(32) NOT NULL,
+    granted_by VARCHAR(32) NOT NULL,
+    granted_at BIGINT NOT NULL,
+    PRIMARY KEY (connection_id, grantee_id),
+    INDEX connection_grants_grantee_id (grantee_id),
+    FOREIGN KEY (connection_id) REFERENCES connections(id)
+);
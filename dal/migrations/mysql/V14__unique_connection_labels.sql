@@ -0,0 +1,6 @@
+-- At most one personal connection of a user may have a given label, including no label.
+-- MySQL has no partial or expression indexes, the generated column is NULL for shared connections,
+-- which unique indexes do not compare. Labels are never empty, so an empty string stands for no label
+ALTER TABLE connections
+    ADD COLUMN personal_label VARCHAR(64) AS (IF(owner_id IS NULL, COALESCE(label, ''), NULL)) STORED,
+    ADD UNIQUE INDEX connections_user_personal_label (user_id, personal_label);
@@ -0,0 +1,3 @@
+-- The shared connection a pending login reauthorizes, NULL when the login creates or updates a personal connection
+ALTER TABLE login_tickets ADD COLUMN connection_id VARCHAR(32) NULL;
+ALTER TABLE oauth2_authorization_start ADD COLUMN connection_id VARCHAR(32) NULL;
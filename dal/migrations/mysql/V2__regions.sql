@@ -0,0 +1,5 @@
+ALTER TABLE users
+    ADD COLUMN region ENUM('NL', 'BE', 'UK', 'DE', 'US', 'ES', 'FR') NOT NULL DEFAULT 'NL';
+
+ALTER TABLE oauth2_authorization_start
+    ADD COLUMN region ENUM('NL', 'BE', 'UK', 'DE', 'US', 'ES', 'FR') NOT NULL DEFAULT 'NL';
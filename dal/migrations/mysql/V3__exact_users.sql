@@ -0,0 +1,9 @@
+CREATE TABLE exact_users (
+    user_id VARCHAR(32) NOT NULL,
+    exact_user_id VARCHAR(36) NOT NULL,
+    full_name TEXT NOT NULL,
+    email TEXT NOT NULL,
+    current_division BIGINT NOT NULL,
+    PRIMARY KEY (user_id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
@@ -0,0 +1,4 @@
+ALTER TABLE users
+    ADD COLUMN reauthorization_required BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN refresh_failures INT UNSIGNED NOT NULL DEFAULT 0,
+    ADD COLUMN refresh_retry_at BIGINT NULL;
@@ -0,0 +1 @@
+CREATE INDEX oauth2_tokens_type_expiry ON oauth2_tokens (token_type, expiry);
@@ -0,0 +1,7 @@
+CREATE TABLE refresh_leases (
+    user_id VARCHAR(32) NOT NULL,
+    holder VARCHAR(32) NOT NULL,
+    expires_at BIGINT NOT NULL,
+    PRIMARY KEY (user_id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
@@ -0,0 +1,2 @@
+-- Encrypted tokens are longer than their plaintext
+ALTER TABLE oauth2_tokens MODIFY token TEXT NOT NULL;
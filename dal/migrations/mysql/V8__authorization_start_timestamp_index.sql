@@ -0,0 +1 @@
+CREATE INDEX oauth2_authorization_start_timestamp ON oauth2_authorization_start (timestamp);
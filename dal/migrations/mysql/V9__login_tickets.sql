@@ -0,0 +1,11 @@
+CREATE TABLE login_tickets (
+    id VARCHAR(32) NOT NULL,
+    user_id VARCHAR(32) NOT NULL,
+    timestamp BIGINT NOT NULL,
+    caller TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    region ENUM('NL', 'BE', 'UK', 'DE', 'US', 'ES', 'FR') NOT NULL,
+    PRIMARY KEY (id),
+    INDEX (timestamp),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
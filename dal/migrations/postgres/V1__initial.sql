@@ -0,0 +1,63 @@
+CREATE TABLE users (
+    id VARCHAR(32) NOT NULL,
+    region VARCHAR(2) NOT NULL DEFAULT 'NL' CHECK (region IN ('NL', 'BE', 'UK', 'DE', 'US', 'ES', 'FR')),
+    reauthorization_required BOOLEAN NOT NULL DEFAULT FALSE,
+    refresh_failures INT NOT NULL DEFAULT 0,
+    refresh_retry_at BIGINT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE oauth2_authorization_start (
+    id VARCHAR(32) NOT NULL,
+    user_id VARCHAR(32) NOT NULL,
+    timestamp BIGINT NOT NULL,
+    caller TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    region VARCHAR(2) NOT NULL DEFAULT 'NL' CHECK (region IN ('NL', 'BE', 'UK', 'DE', 'US', 'ES', 'FR')),
+    PRIMARY KEY (id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX oauth2_authorization_start_timestamp ON oauth2_authorization_start (timestamp);
+
+CREATE TABLE oauth2_tokens (
+    user_id VARCHAR(32) NOT NULL,
+    token TEXT NOT NULL,
+    token_type VARCHAR(7) NOT NULL CHECK (token_type IN ('Access', 'Refresh')),
+    expiry BIGINT NOT NULL,
+    PRIMARY KEY (user_id, token_type),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX oauth2_tokens_type_expiry ON oauth2_tokens (token_type, expiry);
+
+CREATE TABLE exact_users (
+    user_id VARCHAR(32) NOT NULL,
+    exact_user_id VARCHAR(36) NOT NULL,
+    full_name TEXT NOT NULL,
+    email TEXT NOT NULL,
+    current_division BIGINT NOT NULL,
+    PRIMARY KEY (user_id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE TABLE refresh_leases (
+    user_id VARCHAR(32) NOT NULL,
+    holder VARCHAR(32) NOT NULL,
+    expires_at BIGINT NOT NULL,
+    PRIMARY KEY (user_id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE TABLE login_tickets (
+    id VARCHAR(32) NOT NULL,
+    user_id VARCHAR(32) NOT NULL,
+    timestamp BIGINT NOT NULL,
+    caller TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    region VARCHAR(2) NOT NULL CHECK (region IN ('NL', 'BE', 'UK', 'DE', 'US', 'ES', 'FR')),
+    PRIMARY KEY (id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX login_tickets_timestamp ON login_tickets (timestamp);
@@ -0,0 +1,18 @@
+-- Actions and outcomes are validated by the application,
+-- so that recording a new kind of action does not require a migration
+CREATE TABLE audit_events (
+    id BIGSERIAL NOT NULL,
+    timestamp BIGINT NOT NULL,
+    actor VARCHAR(64) NOT NULL,
+    user_id VARCHAR(32) NULL,
+    action VARCHAR(32) NOT NULL,
+    outcome VARCHAR(16) NOT NULL,
+    detail TEXT NULL,
+    ip VARCHAR(45) NULL,
+    user_agent TEXT NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE INDEX audit_events_timestamp ON audit_events (timestamp);
+CREATE INDEX audit_events_actor ON audit_events (actor, id);
+CREATE INDEX audit_events_user_id ON audit_events (user_id, id);
@@ -0,0 +1,72 @@
+-- A user may hold several Exact connections, each with its own region, refresh state and tokens.
+-- Every existing user with tokens gets a single unlabelled connection, which reuses the ID of the user
+CREATE TABLE connections (
+    id VARCHAR(32) NOT NULL,
+    user_id VARCHAR(32) NOT NULL,
+    label VARCHAR(64) NULL,
+    region VARCHAR(2) NOT NULL DEFAULT 'NL' CHECK (region IN ('NL', 'BE', 'UK', 'DE', 'US', 'ES', 'FR')),
+    reauthorization_required BOOLEAN NOT NULL DEFAULT FALSE,
+    refresh_failures INT NOT NULL DEFAULT 0,
+    refresh_retry_at BIGINT NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX connections_user_id ON connections (user_id);
+
+INSERT INTO connections (id, user_id, region, reauthorization_required, refresh_failures, refresh_retry_at)
+    SELECT id, id, region, reauthorization_required, refresh_failures, refresh_retry_at FROM users
+    WHERE id IN (SELECT user_id FROM oauth2_tokens);
+
+CREATE TABLE oauth2_tokens_new (
+    connection_id VARCHAR(32) NOT NULL,
+    token TEXT NOT NULL,
+    token_type VARCHAR(7) NOT NULL CHECK (token_type IN ('Access', 'Refresh')),
+    expiry BIGINT NOT NULL,
+    PRIMARY KEY (connection_id, token_type),
+    FOREIGN KEY (connection_id) REFERENCES connections(id)
+);
+
+INSERT INTO oauth2_tokens_new (connection_id, token, token_type, expiry)
+    SELECT user_id, token, token_type, expiry FROM oauth2_tokens;
+DROP TABLE oauth2_tokens;
+ALTER TABLE oauth2_tokens_new RENAME TO oauth2_tokens;
+CREATE INDEX oauth2_tokens_type_expiry ON oauth2_tokens (token_type, expiry);
+
+CREATE TABLE exact_users_new (
+    connection_id VARCHAR(32) NOT NULL,
+    exact_user_id VARCHAR(36) NOT NULL,
+    full_name TEXT NOT NULL,
+    email TEXT NOT NULL,
+    current_division BIGINT NOT NULL,
+    PRIMARY KEY (connection_id),
+    FOREIGN KEY (connection_id) REFERENCES connections(id)
+);
+
+INSERT INTO exact_users_new (connection_id, exact_user_id, full_name, email, current_division)
+    SELECT user_id, exact_user_id, full_name, email, current_division FROM exact_users
+    WHERE user_id IN (SELECT id FROM connections);
+DROP TABLE exact_users;
+ALTER TABLE exact_users_new RENAME TO exact_users;
+
+-- Leases are short-lived, there is nothing to carry over
+DROP TABLE refresh_leases;
+CREATE TABLE refresh_leases (
+    connection_id VARCHAR(32) NOT NULL,
+    holder VARCHAR(32) NOT NULL,
+    expires_at BIGINT NOT NULL,
+    PRIMARY KEY (connection_id),
+    FOREIGN KEY (connection_id) REFERENCES connections(id)
+);
+
+ALTER TABLE oauth2_authorization_start ADD COLUMN connection_label VARCHAR(64) NULL;
+
+ALTER TABLE login_tickets ADD COLUMN connection_label VARCHAR(64) NULL;
+
+ALTER TABLE users
+    DROP COLUMN region,
+    DROP COLUMN reauthorization_required,
+    DROP COLUMN refresh_failures,
+    DROP COLUMN refresh_retry_at;
+
+ALTER TABLE audit_events ADD COLUMN connection_id VARCHAR(32) NULL;
@@ -0,0 +1,23 @@
+-- Organisations and service identities may own connections, which are shared with the users they are granted to
+CREATE TABLE connection_owners (
+    id VARCHAR(32) NOT NULL,
+    name VARCHAR(64) NOT NULL,
+    kind VARCHAR(12) NOT NULL CHECK (kind IN ('Organisation', 'Service')),
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (id)
+);
+
+ALTER TABLE connections ADD COLUMN owner_id VARCHAR(32) NULL REFERENCES connection_owners(id);
+CREATE INDEX connections_owner_id ON connections (owner_id);
+
+-- Grantees are MrAuth users or service accounts, which need not have logged in to ExactAuth themselves
+CREATE TABLE connection_grants (
+    connection_id VARCHAR(32) NOT NULL,
+    grantee_id VARCHAR(32) NOT NULL,
+    granted_by VARCHAR(32) NOT NULL,
+    granted_at BIGINT NOT NULL,
+    PRIMARY KEY (connection_id, grantee_id),
+    FOREIGN KEY (connection_id) REFERENCES connections(id)
+);
+
+CREATE INDEX connection_grants_grantee_id ON connection_grants (grantee_id);
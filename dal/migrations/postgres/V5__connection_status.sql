@@ -0,0 +1,4 @@
+-- The scopes are those requested in the last completed login, unknown for connections made before they were recorded
+ALTER TABLE connections
+    ADD COLUMN scopes TEXT NULL,
+    ADD COLUMN last_refreshed_at BIGINT NULL;
@@ -0,0 +1,63 @@
+CREATE TABLE users (
+    id TEXT NOT NULL,
+    region TEXT NOT NULL DEFAULT 'NL' CHECK (region IN ('NL', 'BE', 'UK', 'DE', 'US', 'ES', 'FR')),
+    reauthorization_required INTEGER NOT NULL DEFAULT 0,
+    refresh_failures INTEGER NOT NULL DEFAULT 0,
+    refresh_retry_at INTEGER NULL,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE oauth2_authorization_start (
+    id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    caller TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    region TEXT NOT NULL DEFAULT 'NL' CHECK (region IN ('NL', 'BE', 'UK', 'DE', 'US', 'ES', 'FR')),
+    PRIMARY KEY (id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX oauth2_authorization_start_timestamp ON oauth2_authorization_start (timestamp);
+
+CREATE TABLE oauth2_tokens (
+    user_id TEXT NOT NULL,
+    token TEXT NOT NULL,
+    token_type TEXT NOT NULL CHECK (token_type IN ('Access', 'Refresh')),
+    expiry INTEGER NOT NULL,
+    PRIMARY KEY (user_id, token_type),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX oauth2_tokens_type_expiry ON oauth2_tokens (token_type, expiry);
+
+CREATE TABLE exact_users (
+    user_id TEXT NOT NULL,
+    exact_user_id TEXT NOT NULL,
+    full_name TEXT NOT NULL,
+    email TEXT NOT NULL,
+    current_division INTEGER NOT NULL,
+    PRIMARY KEY (user_id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE TABLE refresh_leases (
+    user_id TEXT NOT NULL,
+    holder TEXT NOT NULL,
+    expires_at INTEGER NOT NULL,
+    PRIMARY KEY (user_id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE TABLE login_tickets (
+    id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    caller TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    region TEXT NOT NULL CHECK (region IN ('NL', 'BE', 'UK', 'DE', 'US', 'ES', 'FR')),
+    PRIMARY KEY (id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX login_tickets_timestamp ON login_tickets (timestamp);
@@ -0,0 +1,17 @@
+-- Actions and outcomes are validated by the application,
+-- so that recording a new kind of action does not require a migration
+CREATE TABLE audit_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp INTEGER NOT NULL,
+    actor TEXT NOT NULL,
+    user_id TEXT NULL,
+    action TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    detail TEXT NULL,
+    ip TEXT NULL,
+    user_agent TEXT NULL
+);
+
+CREATE INDEX audit_events_timestamp ON audit_events (timestamp);
+CREATE INDEX audit_events_actor ON audit_events (actor, id);
+CREATE INDEX audit_events_user_id ON audit_events (user_id, id);
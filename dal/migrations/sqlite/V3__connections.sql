@@ -0,0 +1,72 @@
+-- A user may hold several Exact connections, each with its own region, refresh state and tokens.
+-- Every existing user with tokens gets a single unlabelled connection, which reuses the ID of the user
+CREATE TABLE connections (
+    id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    label TEXT NULL,
+    region TEXT NOT NULL DEFAULT 'NL' CHECK (region IN ('NL', 'BE', 'UK', 'DE', 'US', 'ES', 'FR')),
+    reauthorization_required INTEGER NOT NULL DEFAULT 0,
+    refresh_failures INTEGER NOT NULL DEFAULT 0,
+    refresh_retry_at INTEGER NULL,
+    PRIMARY KEY (id),
+    FOREIGN KEY (user_id) REFERENCES users(id)
+);
+
+CREATE INDEX connections_user_id ON connections (user_id);
+
+INSERT INTO connections (id, user_id, region, reauthorization_required, refresh_failures, refresh_retry_at)
+    SELECT id, id, region, reauthorization_required, refresh_failures, refresh_retry_at FROM users
+    WHERE id IN (SELECT user_id FROM oauth2_tokens);
+
+-- SQLite can not change the primary key or foreign keys of a table, so tables are rebuilt instead
+CREATE TABLE oauth2_tokens_new (
+    connection_id TEXT NOT NULL,
+    token TEXT NOT NULL,
+    token_type TEXT NOT NULL CHECK (token_type IN ('Access', 'Refresh')),
+    expiry INTEGER NOT NULL,
+    PRIMARY KEY (connection_id, token_type),
+    FOREIGN KEY (connection_id) REFERENCES connections(id)
+);
+
+INSERT INTO oauth2_tokens_new (connection_id, token, token_type, expiry)
+    SELECT user_id, token, token_type, expiry FROM oauth2_tokens;
+DROP TABLE oauth2_tokens;
+ALTER TABLE oauth2_tokens_new RENAME TO oauth2_tokens;
+CREATE INDEX oauth2_tokens_type_expiry ON oauth2_tokens (token_type, expiry);
+
+CREATE TABLE exact_users_new (
+    connection_id TEXT NOT NULL,
+    exact_user_id TEXT NOT NULL,
+    full_name TEXT NOT NULL,
+    email TEXT NOT NULL,
+    current_division INTEGER NOT NULL,
+    PRIMARY KEY (connection_id),
+    FOREIGN KEY (connection_id) REFERENCES connections(id)
+);
+
+INSERT INTO exact_users_new (connection_id, exact_user_id, full_name, email, current_division)
+    SELECT user_id, exact_user_id, full_name, email, current_division FROM exact_users
+    WHERE user_id IN (SELECT id FROM connections);
+DROP TABLE exact_users;
+ALTER TABLE exact_users_new RENAME TO exact_users;
+
+-- Leases are short-lived, there is nothing to carry over
+DROP TABLE refresh_leases;
+CREATE TABLE refresh_leases (
+    connection_id TEXT NOT NULL,
+    holder TEXT NOT NULL,
+    expires_at INTEGER NOT NULL,
+    PRIMARY KEY (connection_id),
+    FOREIGN KEY (connection_id) REFERENCES connections(id)
+);
+
+ALTER TABLE oauth2_authorization_start ADD COLUMN connection_label TEXT NULL;
+
+ALTER TABLE login_tickets ADD COLUMN connection_label TEXT NULL;
+
+ALTER TABLE users DROP COLUMN region;
+ALTER TABLE users DROP COLUMN reauthorization_required;
+ALTER TABLE users DROP COLUMN refresh_failures;
+ALTER TABLE users DROP COLUMN refresh_retry_at;
+
+ALTER TABLE audit_events ADD COLUMN connection_id TEXT NULL;
@@ -0,0 +1,23 @@
+-- Organisations and service identities may own connections, which are shared with the users they are granted to
+CREATE TABLE connection_owners (
+    id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('Organisation', 'Service')),
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (id)
+);
+
+ALTER TABLE connections ADD COLUMN owner_id TEXT NULL REFERENCES connection_owners(id);
+CREATE INDEX connections_owner_id ON connections (owner_id);
+
+-- Grantees are MrAuth users or service accounts, which need not have logged in to ExactAuth themselves
+CREATE TABLE connection_grants (
+    connection_id TEXT NOT NULL,
+    grantee_id TEXT NOT NULL,
+    granted_by TEXT NOT NULL,
+    granted_at INTEGER NOT NULL,
+    PRIMARY KEY (connection_id, grantee_id),
+    FOREIGN KEY (connection_id) REFERENCES connections(id)
+);
+
+CREATE INDEX connection_grants_grantee_id ON connection_grants (grantee_id);
@@ -0,0 +1,3 @@
+-- The scopes are those requested in the last completed login, unknown for connections made before they were recorded
+ALTER TABLE connections ADD COLUMN scopes TEXT NULL;
+ALTER TABLE connections ADD COLUMN last_refreshed_at INTEGER NULL;
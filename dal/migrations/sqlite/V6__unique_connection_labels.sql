@@ -0,0 +1,3 @@
+-- At most one personal connection of a user may have a given label, including no label.
+-- Labels are never empty, so an empty string stands for no label
+CREATE UNIQUE INDEX connections_user_personal_label ON connections (user_id, COALESCE(label, '')) WHERE owner_id IS NULL;
@@ -0,0 +1,7 @@
+-- The grantee of a grant or revocation, so that it can be pseudonymised when the grantee is purged
+ALTER TABLE audit_events ADD COLUMN grantee_id VARCHAR(32) NULL;
+CREATE INDEX audit_events_grantee_id ON audit_events (grantee_id);
+
+-- Grantees were recorded in the detail before
+UPDATE audit_events SET grantee_id = SUBSTR(detail, 12), detail = NULL WHERE action = 'AccessGranted' AND detail LIKE 'Granted to %';
+UPDATE audit_events SET grantee_id = SUBSTR(detail, 14), detail = NULL WHERE action = 'AccessRevoked' AND detail LIKE 'Revoked from %';
@@ -0,0 +1,108 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use crate::error::DalResult;
+use crate::{Error, Storage, TokenCipher};
+
+/// The database to connect to
+pub enum DatabaseConfig {
+    /// MySQL or MariaDB, requires the `mysql` feature
+    Mysql {
+        host: String,
+        user: String,
+        password: String,
+        database: String,
+    },
+    /// PostgreSQL, requires the `postgres` feature
+    Postgres {
+        host: String,
+        user: String,
+        password: String,
+        database: String,
+    },
+    /// SQLite, requires the `sqlite` feature.
+    /// A path of `:memory:` opens a database that only lives in memory
+    Sqlite {
+        path: String,
+    },
+}
+
+/// The database connection.
+///
+/// `Self` is always considered to be partially equal to another `Self`, irregardless
+/// of what database is used.
+#[derive(Clone)]
+pub struct Database {
+    storage: Arc<dyn Storage>,
+    token_cipher: Arc<TokenCipher>,
+}
+
+impl PartialEq for Database {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Debug for Database {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Database {{ ... }}")
+    }
+}
+
+impl Database {
+    /// Connect to the configured database and apply migrations.
+    /// OAuth2 tokens are encrypted with `token_cipher` before they are stored.
+    ///
+    /// # Errors
+    ///
+    /// - If the backend of the configured database is not enabled
+    /// - If connecting to the database fails
+    /// - If applying the migrations fails
+    pub async fn new(config: DatabaseConfig, token_cipher: TokenCipher) -> DalResult<Self> {
+        let storage: Arc<dyn Storage> = match config {
+            #[cfg(feature = "mysql")]
+            DatabaseConfig::Mysql { host, user, password, database } => {
+                Arc::new(crate::storage::mysql::MysqlStorage::new(&user, &password, &host, &database).await?)
+            },
+            #[cfg(feature = "postgres")]
+            DatabaseConfig::Postgres { host, user, password, database } => {
+                Arc::new(crate::storage::postgres::PostgresStorage::new(&user, &password, &host, &database).await?)
+            },
+            #[cfg(feature = "sqlite")]
+            DatabaseConfig::Sqlite { path } => {
+                Arc::new(crate::storage::sqlite::SqliteStorage::new(&path).await?)
+            },
+            #[cfg(not(all(feature = "mysql", feature = "postgres", feature = "sqlite")))]
+            config => return Err(Error::BackendNotEnabled(config.backend_name())),
+        };
+
+        Ok(Self::from_storage(storage, token_cipher))
+    }
+
+    /// Use a custom storage backend
+    pub fn from_storage(storage: Arc<dyn Storage>, token_cipher: TokenCipher) -> Self {
+        Self {
+            storage,
+            token_cipher: Arc::new(token_cipher),
+        }
+    }
+
+    pub(crate) fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    pub(crate) fn token_cipher(&self) -> &TokenCipher {
+        &self.token_cipher
+    }
+}
+
+impl DatabaseConfig {
+    /// The name of the feature providing the backend
+    #[cfg(not(all(feature = "mysql", feature = "postgres", feature = "sqlite")))]
+    fn backend_name(&self) -> &'static str {
+        match self {
+            Self::Mysql { .. } => "mysql",
+            Self::Postgres { .. } => "postgres",
+            Self::Sqlite { .. } => "sqlite",
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+use crate::{DalResult, Database};
+
+/// A token-related action recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    /// A user started logging in with Exact
+    LoginStarted,
+    /// A user returned from logging in with Exact
+    LoginCompleted,
+    /// An access token was handed out
+    AccessTokenFetched,
+    /// A request was forwarded to Exact with an access token
+    ExactRequestForwarded,
+    /// The tokens of a user were refreshed by the refresh task
+    TokensRefreshed,
+    /// An admin granted access to a shared connection
+    AccessGranted,
+    /// An admin revoked access to a shared connection
+    AccessRevoked,
+    /// An admin created an owner connections can be shared with
+    OwnerCreated,
+    /// An admin shared a connection with an owner, or made it personal again
+    ConnectionOwnerChanged,
+    /// A user deleted a connection
+    ConnectionDeleted,
+    /// An admin deleted a user with everything stored about it
+    UserPurged,
+}
+
+/// Whether the recorded action succeeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown audit action: {0}")]
+pub struct UnknownAuditAction(pub String);
+
+#[derive(Debug, Error)]
+#[error("Unknown audit outcome: {0}")]
+pub struct UnknownAuditOutcome(pub String);
+
+/// A recorded audit event
+pub struct AuditEvent {
+    pub id: i64,
+    pub timestamp: i64,
+    /// The MrAuth user that performed the action, or [AUDIT_ACTOR_SYSTEM]
+    pub actor: String,
+    /// The user whose tokens the action concerns, if any
+    pub user_id: Option<String>,
+    /// The connection whose tokens the action concerns, if known
+    pub connection_id: Option<String>,
+    /// The MrAuth user or service account access was granted to or revoked from, for grants and revocations
+    pub grantee_id: Option<String>,
+    pub action: AuditAction,
+    pub outcome: AuditOutcome,
+    /// Additional information, e.g. why the action failed. Never contains user IDs, which could not be pseudonymised
+    pub detail: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// An audit event to be recorded.
+/// The ID and timestamp are assigned when it is recorded
+#[derive(Clone)]
+pub struct NewAuditEvent {
+    pub actor: String,
+    pub user_id: Option<String>,
+    pub connection_id: Option<String>,
+    pub grantee_id: Option<String>,
+    pub action: AuditAction,
+    pub outcome: AuditOutcome,
+    pub detail: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Which audit events to list.
+/// Every field that is set must match, fields that are not set match everything
+#[derive(Clone, Default)]
+pub struct AuditEventFilter {
+    pub actor: Option<String>,
+    pub user_id: Option<String>,
+    pub action: Option<AuditAction>,
+    pub outcome: Option<AuditOutcome>,
+    /// Only events recorded at or after this UNIX timestamp
+    pub from: Option<i64>,
+    /// Only events recorded at or before this UNIX timestamp
+    pub to: Option<i64>,
+    /// Only events with an ID lower than this, used to fetch the next page
+    pub before_id: Option<i64>,
+}
+
+/// The actor of actions not performed on behalf of a MrAuth user, e.g. by the refresh task
+pub const AUDIT_ACTOR_SYSTEM: &str = "system";
+
+impl AuditEvent {
+    /// Record the event at the current time
+    pub async fn create(db: Database, event: &NewAuditEvent) -> DalResult<()> {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        db.storage().create_audit_event(now, event).await
+    }
+
+    /// List at most `limit` events matching the filter, newest first
+    pub async fn list(db: Database, filter: &AuditEventFilter, limit: u32) -> DalResult<Vec<Self>> {
+        db.storage().list_audit_events(filter, limit).await
+    }
+}
+
+impl AuditAction {
+    /// The representation of the action as stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::LoginStarted => "LoginStarted",
+            Self::LoginCompleted => "LoginCompleted",
+            Self::AccessTokenFetched => "AccessTokenFetched",
+            Self::ExactRequestForwarded => "ExactRequestForwarded",
+            Self::TokensRefreshed => "TokensRefreshed",
+            Self::AccessGranted => "AccessGranted",
+            Self::AccessRevoked => "AccessRevoked",
+            Self::OwnerCreated => "OwnerCreated",
+            Self::ConnectionOwnerChanged => "ConnectionOwnerChanged",
+            Self::ConnectionDeleted => "ConnectionDeleted",
+            Self::UserPurged => "UserPurged",
+        }
+    }
+}
+
+impl Display for AuditAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = UnknownAuditAction;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "LoginStarted" => Ok(Self::LoginStarted),
+            "LoginCompleted" => Ok(Self::LoginCompleted),
+            "AccessTokenFetched" => Ok(Self::AccessTokenFetched),
+            "ExactRequestForwarded" => Ok(Self::ExactRequestForwarded),
+            "TokensRefreshed" => Ok(Self::TokensRefreshed),
+            "AccessGranted" => Ok(Self::AccessGranted),
+            "AccessRevoked" => Ok(Self::AccessRevoked),
+            "OwnerCreated" => Ok(Self::OwnerCreated),
+            "ConnectionOwnerChanged" => Ok(Self::ConnectionOwnerChanged),
+            "ConnectionDeleted" => Ok(Self::ConnectionDeleted),
+            "UserPurged" => Ok(Self::UserPurged),
+            _ => Err(UnknownAuditAction(s.to_string()))
+        }
+    }
+}
+
+impl AuditOutcome {
+    /// The representation of the outcome as stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Success => "Success",
+            Self::Failure => "Failure",
+        }
+    }
+}
+
+impl Display for AuditOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for AuditOutcome {
+    type Err = UnknownAuditOutcome;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Success" => Ok(Self::Success),
+            "Failure" => Ok(Self::Failure),
+            _ => Err(UnknownAuditOutcome(s.to_string()))
+        }
+    }
+}
@@ -0,0 +1,274 @@
+use crate::{ConnectionRecord, DalResult, Database, generate_id, OAuth2Token, OAuth2Tokentype, Region, TokenRecord, User};
+
+/// The maximum length of a connection label, as set in the database schemas
+pub const MAX_CONNECTION_LABEL_LEN: usize = 64;
+
+/// A connection to a single Exact Online account, owned by a [User] or shared by an [Owner](crate::Owner).
+/// Every connection has its own region, tokens and refresh state.
+#[derive(Clone)]
+pub struct Connection {
+    pub(crate) db: Database,
+    pub id: String,
+    /// The ID of the user who logged in to Exact for the connection.
+    /// Unless the connection is shared, the user also owns it
+    pub user_id: String,
+    /// The owner of the connection, if it is shared
+    pub owner_id: Option<String>,
+    /// Distinguishes the personal connections of a single user. The database allows at most one personal connection of a user per label, and one without a label
+    pub label: Option<String>,
+    pub region: Region,
+    /// The Exact grant was revoked or expired, the user must log in again
+    pub reauthorization_required: bool,
+    /// The number of consecutive failed attempts to refresh the connection's tokens
+    pub refresh_failures: u32,
+    /// UNIX timestamp before which refreshing should not be attempted again
+    pub refresh_retry_at: Option<i64>,
+    /// The Exact scopes requested in the last login, `None` if unknown.
+    /// Exact does not report which scopes the user actually granted
+    pub scopes: Option<String>,
+    /// UNIX timestamp at which the connection's tokens were last refreshed by the refresher, `None` if they never were
+    pub last_refreshed_at: Option<i64>,
+}
+
+impl User {
+    /// List the connections of the user which are not shared
+    pub async fn list_connections(&self) -> DalResult<Vec<Connection>> {
+        let connections = self.db.storage().list_connections(&self.id).await?
+            .into_iter()
+            .map(|record| Connection::from_record(self.db.clone(), record))
+            .collect();
+        Ok(connections)
+    }
+
+    /// Get the connection with the provided ID, if it is owned by the user and not shared
+    pub async fn get_connection(&self, id: &str) -> DalResult<Option<Connection>> {
+        let record = match self.db.storage().get_connection(id).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        if record.user_id != self.id || record.owner_id.is_some() {
+            return Ok(None);
+        }
+
+        Ok(Some(Connection::from_record(self.db.clone(), record)))
+    }
+
+    /// Get the connection of the user with the provided label, if it is not shared.
+    /// A label of `None` selects the unlabelled connection
+    pub async fn get_connection_by_label(&self, label: Option<&str>) -> DalResult<Option<Connection>> {
+        let record = match self.db.storage().get_connection_by_label(&self.id, label).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(Connection::from_record(self.db.clone(), record)))
+    }
+
+    /// Create a personal connection with the label. If the user already has a personal connection with the label,
+    /// its region and scopes are updated and it is returned instead
+    pub async fn create_connection(&self, label: Option<&str>, region: Region, scopes: &str) -> DalResult<Connection> {
+        let record = ConnectionRecord {
+            id: generate_id(32),
+            user_id: self.id.clone(),
+            owner_id: None,
+            label: label.map(str::to_string),
+            region,
+            reauthorization_required: false,
+            refresh_failures: 0,
+            refresh_retry_at: None,
+            scopes: Some(scopes.to_string()),
+            last_refreshed_at: None,
+        };
+        let record = self.db.storage().create_connection(&record).await?;
+
+        Ok(Connection::from_record(self.db.clone(), record))
+    }
+}
+
+impl Connection {
+    pub(crate) fn from_record(db: Database, record: ConnectionRecord) -> Self {
+        Self {
+            db,
+            id: record.id,
+            user_id: record.user_id,
+            owner_id: record.owner_id,
+            label: record.label,
+            region: record.region,
+            reauthorization_required: record.reauthorization_required,
+            refresh_failures: record.refresh_failures,
+            refresh_retry_at: record.refresh_retry_at,
+            scopes: record.scopes,
+            last_refreshed_at: record.last_refreshed_at,
+        }
+    }
+
+    /// Get any connection by its ID, regardless of who may use it
+    pub async fn get_by_id(db: Database, id: &str) -> DalResult<Option<Self>> {
+        let record = match db.storage().get_connection(id).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(Self::from_record(db, record)))
+    }
+
+    /// List all shared connections the MrAuth user or service account was granted access to
+    pub async fn list_granted(db: Database, grantee_id: &str) -> DalResult<Vec<Self>> {
+        let connections = db.storage().list_granted_connections(grantee_id).await?
+            .into_iter()
+            .map(|record| Self::from_record(db.clone(), record))
+            .collect();
+        Ok(connections)
+    }
+
+    /// List all shared connections the user logged in to
+    pub async fn list_shared_of_user(db: Database, user_id: &str) -> DalResult<Vec<Self>> {
+        let connections = db.storage().list_shared_connections_of_user(user_id).await?
+            .into_iter()
+            .map(|record| Self::from_record(db.clone(), record))
+            .collect();
+        Ok(connections)
+    }
+
+    /// List all connections whose access token expires at or before `expires_before`, ordered by expiry.
+    /// Connections which must reauthorize, or which are backing off until after `now`, are excluded.
+    pub async fn list_refresh_due(db: Database, expires_before: i64, now: i64) -> DalResult<Vec<Self>> {
+        let connections = db.storage().list_refresh_due(expires_before, now).await?
+            .into_iter()
+            .map(|record| Self::from_record(db.clone(), record))
+            .collect();
+        Ok(connections)
+    }
+
+    /// The UNIX timestamp at which the next connection's access token should be refreshed,
+    /// i.e. `window_sec` before its expiry, or after its refresh backoff, whichever comes last.
+    /// `None` if there are no tokens to refresh.
+    pub async fn next_refresh_due(db: Database, window_sec: i64) -> DalResult<Option<i64>> {
+        db.storage().next_refresh_due(window_sec).await
+    }
+
+    pub async fn set_region(&mut self, region: Region) -> DalResult<()> {
+        self.db.storage().set_connection_region(&self.id, region).await?;
+
+        self.region = region;
+        Ok(())
+    }
+
+    /// The Exact scopes requested in the last login, `None` if unknown
+    pub fn requested_scopes(&self) -> Option<Vec<&str>> {
+        self.scopes.as_deref()
+            .map(|scopes| scopes.split_whitespace().collect())
+    }
+
+    /// The scopes in `required` which were not requested in the last login.
+    /// For connections made before scopes were recorded every required scope is missing, the user must log in again
+    pub fn missing_scopes<'a>(&self, required: &[&'a str]) -> Vec<&'a str> {
+        let requested = self.requested_scopes().unwrap_or_default();
+
+        required.iter()
+            .filter(|scope| !requested.contains(scope))
+            .copied()
+            .collect()
+    }
+
+    /// Set the scopes requested when the user logged in again
+    pub async fn set_scopes(&mut self, scopes: &str) -> DalResult<()> {
+        self.db.storage().set_connection_scopes(&self.id, scopes).await?;
+
+        self.scopes = Some(scopes.to_string());
+        Ok(())
+    }
+
+    /// Record that the connection's tokens were refreshed at `refreshed_at`
+    pub async fn set_last_refreshed_at(&mut self, refreshed_at: i64) -> DalResult<()> {
+        self.db.storage().set_connection_last_refreshed_at(&self.id, refreshed_at).await?;
+
+        self.last_refreshed_at = Some(refreshed_at);
+        Ok(())
+    }
+
+    /// Share the connection by transferring it to the owner, or make it personal again with `None`.
+    /// Existing grants are kept, but only apply while the connection is shared
+    pub async fn set_owner(&mut self, owner_id: Option<&str>) -> DalResult<()> {
+        self.db.storage().set_connection_owner(&self.id, owner_id).await?;
+
+        self.owner_id = owner_id.map(str::to_string);
+        Ok(())
+    }
+
+    /// Delete the connection, with its tokens and everything else stored about it.
+    /// Logins its user started but did not complete are deleted as well, as they may be for this connection
+    pub async fn delete(self) -> DalResult<()> {
+        self.db.storage().delete_connection(&self.id).await
+    }
+
+    /// Mark that the connection's Exact grant is no longer valid.
+    /// The connection's tokens will not be refreshed until the user logs in again.
+    pub async fn set_reauthorization_required(&mut self) -> DalResult<()> {
+        self.db.storage().set_reauthorization_required(&self.id).await?;
+
+        self.reauthorization_required = true;
+        self.refresh_retry_at = None;
+        Ok(())
+    }
+
+    /// Record a failed attempt at refreshing the connection's tokens.
+    /// No new attempt should be made before `retry_at`
+    pub async fn record_refresh_failure(&mut self, retry_at: i64) -> DalResult<()> {
+        self.db.storage().record_refresh_failure(&self.id, retry_at).await?;
+
+        self.refresh_failures += 1;
+        self.refresh_retry_at = Some(retry_at);
+        Ok(())
+    }
+
+    /// Clear any refresh failures and reauthorization requirement,
+    /// after the connection's tokens were successfully refreshed or obtained
+    pub async fn reset_refresh_state(&mut self) -> DalResult<()> {
+        self.db.storage().reset_refresh_state(&self.id).await?;
+
+        self.reauthorization_required = false;
+        self.refresh_failures = 0;
+        self.refresh_retry_at = None;
+        Ok(())
+    }
+
+    /// Store the connection's access and refresh token together, in a single transaction.
+    /// Tokens are always stored as a pair, so that a failure can not leave a mismatched pair behind
+    pub async fn set_token_pair(&self, access_token: &str, access_expiry: i64, refresh_token: &str, refresh_expiry: i64) -> DalResult<()> {
+        let access = TokenRecord {
+            token: self.db.token_cipher().encrypt(access_token, &self.id, OAuth2Tokentype::Access)?,
+            expiry: access_expiry,
+        };
+        let refresh = TokenRecord {
+            token: self.db.token_cipher().encrypt(refresh_token, &self.id, OAuth2Tokentype::Refresh)?,
+            expiry: refresh_expiry,
+        };
+
+        self.db.storage().set_token_pair(&self.id, &access, &refresh).await
+    }
+
+    pub async fn get_access_token(&self) -> DalResult<Option<OAuth2Token>> {
+        self.get_token(OAuth2Tokentype::Access).await
+    }
+
+    pub async fn get_refresh_token(&self) -> DalResult<Option<OAuth2Token>> {
+        self.get_token(OAuth2Tokentype::Refresh).await
+    }
+
+    async fn get_token(&self, token_type: OAuth2Tokentype) -> DalResult<Option<OAuth2Token>> {
+        let record = match self.db.storage().get_token(&self.id, token_type).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        let token = self.db.token_cipher().decrypt(&record.token, &self.id, token_type)?;
+
+        Ok(Some(OAuth2Token {
+            token,
+            expiry: record.expiry,
+            token_type,
+        }))
+    }
+}
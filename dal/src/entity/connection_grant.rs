@@ -0,0 +1,65 @@
+use crate::{Connection, ConnectionGrantRecord, DalResult};
+
+/// Access to a shared [Connection] granted to a MrAuth user or service account
+pub struct ConnectionGrant {
+    pub connection_id: String,
+    /// The MrAuth user ID of the user or service account that may use the connection
+    pub grantee_id: String,
+    /// The MrAuth user ID of the admin that granted access
+    pub granted_by: String,
+    pub granted_at: i64,
+}
+
+impl From<ConnectionGrantRecord> for ConnectionGrant {
+    fn from(record: ConnectionGrantRecord) -> Self {
+        Self {
+            connection_id: record.connection_id,
+            grantee_id: record.grantee_id,
+            granted_by: record.granted_by,
+            granted_at: record.granted_at,
+        }
+    }
+}
+
+impl Connection {
+    /// Grant `grantee_id` access to the connection.
+    /// Returns `None` if the grantee already had access
+    pub async fn grant(&self, grantee_id: &str, granted_by: &str) -> DalResult<Option<ConnectionGrant>> {
+        let record = ConnectionGrantRecord {
+            connection_id: self.id.clone(),
+            grantee_id: grantee_id.to_string(),
+            granted_by: granted_by.to_string(),
+            granted_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+        };
+
+        if !self.db.storage().create_connection_grant(&record).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(record.into()))
+    }
+
+    /// Revoke the access of `grantee_id` to the connection.
+    /// Returns whether the grantee had access
+    pub async fn revoke(&self, grantee_id: &str) -> DalResult<bool> {
+        self.db.storage().delete_connection_grant(&self.id, grantee_id).await
+    }
+
+    pub async fn list_grants(&self) -> DalResult<Vec<ConnectionGrant>> {
+        let grants = self.db.storage().list_connection_grants(&self.id).await?
+            .into_iter()
+            .map(ConnectionGrant::from)
+            .collect();
+        Ok(grants)
+    }
+
+    /// Whether the MrAuth user may use the connection.
+    /// A connection without owner may only be used by its user,
+    /// a shared connection only by those it was granted to
+    pub async fn is_accessible_by(&self, user_id: &str) -> DalResult<bool> {
+        match &self.owner_id {
+            Some(_) => self.db.storage().has_connection_grant(&self.id, user_id).await,
+            None => Ok(self.user_id == user_id),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use crate::{Connection, DalResult};
+
+/// The Exact Online user that authorized a [Connection],
+/// as reported by Exact's `current/Me` endpoint
+#[derive(Clone)]
+pub struct ExactUser {
+    pub exact_user_id: String,
+    pub full_name: String,
+    pub email: String,
+    pub current_division: i64,
+}
+
+impl Connection {
+    pub async fn set_exact_user(&self, exact_user: &ExactUser) -> DalResult<()> {
+        self.db.storage().set_exact_user(&self.id, exact_user).await
+    }
+
+    pub async fn get_exact_user(&self) -> DalResult<Option<ExactUser>> {
+        self.db.storage().get_exact_user(&self.id).await
+    }
+}
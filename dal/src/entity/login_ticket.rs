@@ -0,0 +1,83 @@
+use crate::{DalResult, Database, Error, generate_id, LoginTicketRecord, Region, User};
+
+/// A single-use ticket to start the Exact login on behalf of a user.
+/// Tickets are created by an authenticated request, so that the user's browser
+/// can start the login without carrying the user's bearer token in the URL.
+pub struct LoginTicket {
+    pub id: String,
+    pub user: User,
+    pub timestamp: i64,
+    pub caller: String,
+    pub exact_scopes: String,
+    pub region: Region,
+    /// The label of the connection the login is for. `None` for the unlabelled connection
+    pub connection_label: Option<String>,
+    /// The shared connection the login reauthorizes, if any
+    pub connection_id: Option<String>,
+}
+
+impl User {
+    pub async fn create_login_ticket(&self, exact_scopes: &str, caller: &str, region: Region, connection_label: Option<&str>, connection_id: Option<&str>) -> DalResult<LoginTicket> {
+        let id = generate_id(32);
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+        self.db.storage().create_login_ticket(&LoginTicketRecord {
+            id: id.clone(),
+            user_id: self.id.clone(),
+            timestamp: now,
+            caller: caller.to_string(),
+            exact_scopes: exact_scopes.to_string(),
+            region,
+            connection_label: connection_label.map(str::to_string),
+            connection_id: connection_id.map(str::to_string),
+        }).await?;
+
+        Ok(LoginTicket {
+            id,
+            user: self.clone(),
+            timestamp: now,
+            caller: caller.to_string(),
+            exact_scopes: exact_scopes.to_string(),
+            region,
+            connection_label: connection_label.map(str::to_string),
+            connection_id: connection_id.map(str::to_string),
+        })
+    }
+}
+
+impl LoginTicket {
+    /// Retrieve and delete the ticket with the provided ID, atomically.
+    /// A ticket can thus only be consumed once, concurrent calls will see `None`.
+    pub async fn consume(db: Database, id: &str) -> DalResult<Option<Self>> {
+        let record = match db.storage().consume_login_ticket(id).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        let user = User::get_by_id(db, &record.user_id).await?
+            .ok_or(Error::InvalidState("Missing user for existing login ticket".into()))?;
+
+        Ok(Some(Self {
+            id: record.id,
+            user,
+            timestamp: record.timestamp,
+            caller: record.caller,
+            exact_scopes: record.exact_scopes,
+            region: record.region,
+            connection_label: record.connection_label,
+            connection_id: record.connection_id,
+        }))
+    }
+
+    /// Whether the ticket is older than `ttl_sec` seconds
+    pub fn is_expired(&self, ttl_sec: i64) -> bool {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        now - self.timestamp > ttl_sec
+    }
+
+    /// Delete all tickets created before `timestamp`.
+    /// Returns the number of deleted tickets
+    pub async fn delete_created_before(db: Database, timestamp: i64) -> DalResult<u64> {
+        db.storage().delete_login_tickets_before(timestamp).await
+    }
+}
@@ -1,2 +1,26 @@
 mod user;
-pub use user::*;
\ No newline at end of file
+pub use user::*;
+
+mod connection;
+pub use connection::*;
+
+mod owner;
+pub use owner::*;
+
+mod connection_grant;
+pub use connection_grant::*;
+
+mod region;
+pub use region::*;
+
+mod exact_user;
+pub use exact_user::*;
+
+mod refresh_lease;
+pub use refresh_lease::*;
+
+mod login_ticket;
+pub use login_ticket::*;
+
+mod audit_event;
+pub use audit_event::*;
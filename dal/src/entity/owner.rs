@@ -0,0 +1,105 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+use crate::{Connection, DalResult, Database, generate_id, OwnerRecord};
+
+/// The maximum length of an owner name, as set in the database schemas
+pub const MAX_OWNER_NAME_LEN: usize = 64;
+
+/// An organisation or service identity owning shared [Connection]s.
+/// Users may only use a shared connection if they were granted access to it
+#[derive(Clone)]
+pub struct Owner {
+    pub(crate) db: Database,
+    pub id: String,
+    pub name: String,
+    pub kind: OwnerKind,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OwnerKind {
+    Organisation,
+    Service,
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown owner kind: {0}")]
+pub struct UnknownOwnerKind(pub String);
+
+impl Owner {
+    pub async fn list_all(db: Database) -> DalResult<Vec<Self>> {
+        let owners = db.storage().list_owners().await?
+            .into_iter()
+            .map(|record| Self::from_record(db.clone(), record))
+            .collect();
+        Ok(owners)
+    }
+
+    pub async fn get_by_id(db: Database, id: &str) -> DalResult<Option<Self>> {
+        let record = match db.storage().get_owner(id).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(Self::from_record(db, record)))
+    }
+
+    pub async fn create(db: Database, name: &str, kind: OwnerKind) -> DalResult<Self> {
+        let record = OwnerRecord {
+            id: generate_id(32),
+            name: name.to_string(),
+            kind,
+            created_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+        };
+        db.storage().create_owner(&record).await?;
+
+        Ok(Self::from_record(db, record))
+    }
+
+    fn from_record(db: Database, record: OwnerRecord) -> Self {
+        Self {
+            db,
+            id: record.id,
+            name: record.name,
+            kind: record.kind,
+            created_at: record.created_at,
+        }
+    }
+
+    pub async fn list_connections(&self) -> DalResult<Vec<Connection>> {
+        let connections = self.db.storage().list_owner_connections(&self.id).await?
+            .into_iter()
+            .map(|record| Connection::from_record(self.db.clone(), record))
+            .collect();
+        Ok(connections)
+    }
+}
+
+impl OwnerKind {
+    /// The representation of the kind as stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Organisation => "Organisation",
+            Self::Service => "Service",
+        }
+    }
+}
+
+impl Display for OwnerKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for OwnerKind {
+    type Err = UnknownOwnerKind;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Organisation" => Ok(Self::Organisation),
+            "Service" => Ok(Self::Service),
+            _ => Err(UnknownOwnerKind(s.to_string()))
+        }
+    }
+}
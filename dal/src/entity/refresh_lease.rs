@@ -0,0 +1,52 @@
+use crate::{Connection, DalResult, Database, generate_id};
+
+/// Identifies a single ExactAuth instance when acquiring leases.
+/// Every instance should create exactly one holder.
+#[derive(Debug, Clone)]
+pub struct LeaseHolder(String);
+
+impl LeaseHolder {
+    pub fn new() -> Self {
+        Self(generate_id(32))
+    }
+}
+
+impl Default for LeaseHolder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An exclusive right to refresh the tokens of a connection.
+/// Exact rotates refresh tokens, so only one instance may refresh a connection's tokens at a time.
+/// The lease should be released when refreshing is done, if it is not it expires on its own.
+pub struct RefreshLease {
+    db: Database,
+    connection_id: String,
+    holder: LeaseHolder,
+}
+
+impl Connection {
+    /// Try to acquire the refresh lease of the connection for `duration_sec` seconds.
+    /// If `holder` already holds the lease it is extended.
+    /// Returns `None` if the lease is held by another holder.
+    pub async fn try_acquire_refresh_lease(&self, holder: &LeaseHolder, duration_sec: i64) -> DalResult<Option<RefreshLease>> {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+        if !self.db.storage().try_acquire_refresh_lease(&self.id, &holder.0, now, now + duration_sec).await? {
+            return Ok(None);
+        }
+
+        Ok(Some(RefreshLease {
+            db: self.db.clone(),
+            connection_id: self.id.clone(),
+            holder: holder.clone(),
+        }))
+    }
+}
+
+impl RefreshLease {
+    pub async fn release(self) -> DalResult<()> {
+        self.db.storage().release_refresh_lease(&self.connection_id, &self.holder.0).await
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The Exact Online region an account is hosted in.
+/// Every region has its own host, tokens issued by one region are not valid in another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Region {
+    #[default]
+    Nl,
+    Be,
+    Uk,
+    De,
+    Us,
+    Es,
+    Fr,
+}
+
+#[derive(Debug, Error)]
+#[error("Unknown region: {0}")]
+pub struct UnknownRegion(pub String);
+
+impl Region {
+    /// The representation of the region as used in the database schema
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Nl => "NL",
+            Self::Be => "BE",
+            Self::Uk => "UK",
+            Self::De => "DE",
+            Self::Us => "US",
+            Self::Es => "ES",
+            Self::Fr => "FR",
+        }
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Region {
+    type Err = UnknownRegion;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "NL" => Ok(Self::Nl),
+            "BE" => Ok(Self::Be),
+            "UK" => Ok(Self::Uk),
+            "DE" => Ok(Self::De),
+            "US" => Ok(Self::Us),
+            "ES" => Ok(Self::Es),
+            "FR" => Ok(Self::Fr),
+            _ => Err(UnknownRegion(s.to_string()))
+        }
+    }
+}
@@ -1,10 +1,12 @@
-use mysql::{params, PooledConn, Row};
-use mysql::prelude::Queryable;
-use crate::{DalResult, Error, generate_id, Mysql};
+use crate::{AuthorizationStartRecord, DalResult, Database, Error, generate_id, Region, UserRecord};
 
+/// Prefix of the pseudonym which replaces the ID of a purged user in the audit log
+pub const PURGED_USER_PREFIX: &str = "purged-";
+
+/// A MrAuth user, which may hold any number of [Connection](crate::Connection)s to Exact
 #[derive(Clone)]
 pub struct User {
-    mysql: Mysql,
+    pub(crate) db: Database,
     pub id: String,
 }
 
@@ -14,20 +16,48 @@ pub struct AuthorizationStart {
     pub timestamp: i64,
     pub caller: String,
     pub exact_scopes: String,
+    pub region: Region,
+    /// The label of the connection the login is for. `None` for the unlabelled connection
+    pub connection_label: Option<String>,
+    /// The shared connection the login reauthorizes, if any
+    pub connection_id: Option<String>,
+}
+
+impl AuthorizationStart {
+    /// Whether the authorization start is older than `ttl_sec` seconds
+    pub fn is_expired(&self, ttl_sec: i64) -> bool {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        now - self.timestamp > ttl_sec
+    }
+
+    /// Delete all authorization starts created before `timestamp`.
+    /// Returns the number of deleted authorization starts
+    pub async fn delete_created_before(db: Database, timestamp: i64) -> DalResult<u64> {
+        db.storage().delete_authorization_starts_before(timestamp).await
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OAuth2Tokentype {
     Access,
     Refresh
 }
 
 impl OAuth2Tokentype {
-    fn get_token_type_string(&self) -> &'static str {
+    pub(crate) fn get_token_type_string(&self) -> &'static str {
         match self {
             Self::Access => TOKEN_TYPE_ACCESS,
             Self::Refresh => TOKEN_TYPE_REFRESH,
         }
     }
+
+    pub(crate) fn from_token_type_string(token_type: &str) -> Option<Self> {
+        match token_type {
+            TOKEN_TYPE_ACCESS => Some(Self::Access),
+            TOKEN_TYPE_REFRESH => Some(Self::Refresh),
+            _ => None,
+        }
+    }
 }
 
 pub struct OAuth2Token {
@@ -36,66 +66,99 @@ pub struct OAuth2Token {
     pub token_type: OAuth2Tokentype
 }
 
-// Set in the database schema for the oauth2_tokens.token_type
+impl OAuth2Token {
+    /// Re-encrypt all stored tokens which are not encrypted with the current key in the current format,
+    /// including tokens stored before encryption was introduced.
+    /// Returns the number of tokens re-encrypted.
+    pub async fn reencrypt_all(db: Database) -> DalResult<usize> {
+        let mut reencrypted = 0;
+        for stored in db.storage().list_tokens().await? {
+            if db.token_cipher().is_current(&stored.token) {
+                continue;
+            }
+
+            let token = db.token_cipher().decrypt(&stored.token, &stored.connection_id, stored.token_type)?;
+
+            // Only replace if the token was not changed in the meantime, e.g. by a refresh
+            let encrypted = db.token_cipher().encrypt(&token, &stored.connection_id, stored.token_type)?;
+            if db.storage().replace_token(&stored.connection_id, stored.token_type, &stored.token, &encrypted).await? {
+                reencrypted += 1;
+            }
+        }
+
+        Ok(reencrypted)
+    }
+}
+
+// Set in the database schemas for the oauth2_tokens.token_type
 const TOKEN_TYPE_ACCESS: &str = "Access";
 const TOKEN_TYPE_REFRESH: &str = "Refresh";
 
 impl User {
-    pub fn list_all(mysql: Mysql) -> DalResult<Vec<Self>> {
-        let mut conn = mysql.get_conn()?;
-        let rows: Vec<Row> = conn.query("SELECT id FROM users")?;
-        let users = rows.into_iter()
-            .map(|x| User::get_by_id_impl(mysql.clone(), &x.get::<String, &str>("id").unwrap(), &mut conn))
-            .collect::<DalResult<Vec<_>>>()?
+    pub async fn list_all(db: Database) -> DalResult<Vec<Self>> {
+        let users = db.storage().list_users().await?
             .into_iter()
-            .filter_map(|x| x)
-            .collect::<Vec<_>>();
+            .map(|record| Self::from_record(db.clone(), record))
+            .collect();
         Ok(users)
     }
 
-    pub fn get_by_id(mysql: Mysql, id: &str) -> DalResult<Option<Self>> {
-        let mut conn = mysql.get_conn()?;
-        Self::get_by_id_impl(mysql, id, &mut conn)
-    }
-
-    fn get_by_id_impl(mysql: Mysql, id: &str, conn: &mut PooledConn) -> DalResult<Option<Self>> {
-        let _: Row = match conn.exec_first("SELECT 1 FROM users WHERE id = :id", params! {
-            "id" => id
-        })? {
+    pub async fn get_by_id(db: Database, id: &str) -> DalResult<Option<Self>> {
+        let record = match db.storage().get_user(id).await? {
             Some(x) => x,
             None => return Ok(None)
         };
 
-        Ok(Some(Self {
-            mysql,
-            id: id.to_string(),
-        }))
+        Ok(Some(Self::from_record(db, record)))
     }
 
-    pub fn create(mysql: Mysql, id: &str) -> DalResult<Self> {
-        let mut conn = mysql.get_conn()?;
-        conn.exec_drop("INSERT INTO users (id) VALUES (:id)", params! {
-            "id" => id
-        })?;
+    fn from_record(db: Database, record: UserRecord) -> Self {
+        Self {
+            db,
+            id: record.id,
+        }
+    }
+
+    pub async fn create(db: Database, id: &str) -> DalResult<Self> {
+        db.storage().create_user(id).await?;
 
         Ok(Self {
-            mysql,
-            id: id.to_string()
+            db,
+            id: id.to_string(),
         })
     }
 
-    pub fn start_authorization(&self, exact_scopes: &str, caller: &str) -> DalResult<AuthorizationStart> {
+    /// Delete the user with everything stored about it.
+    /// This includes the user's personal connections and its grants to shared connections.
+    /// Fails if the user logged in to a shared connection, see [Connection::list_shared_of_user](crate::Connection::list_shared_of_user).
+    ///
+    /// The user's audit events are kept, but pseudonymised: their IP address and user agent are cleared,
+    /// and the user's ID is replaced by a random pseudonym as actor, subject and grantee.
+    /// Their timestamp, action, outcome, detail and connection ID are kept as is.
+    /// Returns the pseudonym, or `None` if the user did not exist
+    pub async fn purge(db: Database, id: &str) -> DalResult<Option<String>> {
+        let pseudonym = format!("{PURGED_USER_PREFIX}{}", generate_id(32 - PURGED_USER_PREFIX.len()));
+        if db.storage().delete_user(id, &pseudonym).await? {
+            Ok(Some(pseudonym))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn start_authorization(&self, exact_scopes: &str, caller: &str, region: Region, connection_label: Option<&str>, connection_id: Option<&str>) -> DalResult<AuthorizationStart> {
         let id = generate_id(32);
         let now = time::OffsetDateTime::now_utc().unix_timestamp();
 
-        let mut conn = self.mysql.get_conn()?;
-        conn.exec_drop("INSERT INTO oauth2_authorization_start (id, user_id, timestamp, caller, scopes) VALUES (:id, :user_id, :timestamp, :caller, :scopes)", params! {
-            "id" => &id,
-            "user_id" => &self.id,
-            "timestamp" => now,
-            "caller" => caller,
-            "scopes" => exact_scopes
-        })?;
+        self.db.storage().create_authorization_start(&AuthorizationStartRecord {
+            id: id.clone(),
+            user_id: self.id.clone(),
+            timestamp: now,
+            caller: caller.to_string(),
+            exact_scopes: exact_scopes.to_string(),
+            region,
+            connection_label: connection_label.map(str::to_string),
+            connection_id: connection_id.map(str::to_string),
+        }).await?;
 
         Ok(AuthorizationStart {
             user: self.clone(),
@@ -103,92 +166,32 @@ impl User {
             exact_scopes: exact_scopes.to_string(),
             timestamp: now,
             caller: caller.to_string(),
+            region,
+            connection_label: connection_label.map(str::to_string),
+            connection_id: connection_id.map(str::to_string),
         })
     }
 
-    pub fn get_by_authorization_start_id(mysql: Mysql, id: &str) -> DalResult<Option<AuthorizationStart>> {
-        let mut conn = mysql.get_conn()?;
-        let row: Row = match conn.exec_first("SELECT user_id, timestamp, caller, scopes FROM oauth2_authorization_start WHERE id = :id", params! {
-            "id" => id
-        })? {
+    /// Retrieve and delete the authorization start with the provided ID, atomically.
+    /// An authorization start can thus only be consumed once, concurrent calls will see `None`.
+    pub async fn consume_authorization_start(db: Database, id: &str) -> DalResult<Option<AuthorizationStart>> {
+        let record = match db.storage().consume_authorization_start(id).await? {
             Some(x) => x,
             None => return Ok(None)
         };
 
-        let user_id: String = row.get("user_id").unwrap();
-        let timestamp: i64 = row.get("timestamp").unwrap();
-        let caller: String = row.get("caller").unwrap();
-        let scopes: String = row.get("scopes").unwrap();
-
-        let user = Self::get_by_id_impl(mysql, &user_id, &mut conn)?
+        let user = Self::get_by_id(db, &record.user_id).await?
             .ok_or(Error::InvalidState("Missing user for existing oauth2 authorization start".into()))?;
 
         Ok(Some(AuthorizationStart {
             user,
-            id: id.to_string(),
-            timestamp,
-            exact_scopes: scopes,
-            caller
+            id: record.id,
+            timestamp: record.timestamp,
+            exact_scopes: record.exact_scopes,
+            caller: record.caller,
+            region: record.region,
+            connection_label: record.connection_label,
+            connection_id: record.connection_id,
         }))
     }
-
-    pub fn set_access_token(&self, token: &str, expiry: i64) -> DalResult<()> {
-        self.set_token(token, expiry, OAuth2Tokentype::Access)
-    }
-
-    pub fn set_refresh_token(&self, token: &str, expiry: i64) -> DalResult<()> {
-        self.set_token(token, expiry, OAuth2Tokentype::Refresh)
-    }
-
-    pub fn get_access_token(&self) -> DalResult<Option<OAuth2Token>> {
-        self.get_token(OAuth2Tokentype::Access)
-    }
-
-    pub fn get_refresh_token(&self) -> DalResult<Option<OAuth2Token>> {
-        self.get_token(OAuth2Tokentype::Refresh)
-    }
-
-    fn set_token(&self, token: &str, expiry: i64, token_type: OAuth2Tokentype) -> DalResult<()> {
-        let token_type_string = token_type.get_token_type_string();
-        let mut conn = self.mysql.get_conn()?;
-
-        let has_token = self.get_token(token_type)?.is_some();
-        if has_token {
-            conn.exec_drop("UPDATE oauth2_tokens SET token = :token, expiry = :expiry WHERE user_id = :user_id AND token_type = :token_type", params! {
-                "token" => token,
-                "expiry" => expiry,
-                "user_id" => &self.id,
-                "token_type" => token_type_string,
-            })?;
-        } else {
-            conn.exec_drop("INSERT INTO oauth2_tokens (user_id, token, token_type, expiry) VALUES (:user_id, :token, :token_type, :expiry)", params! {
-                "token" => token,
-                "expiry" => expiry,
-                "user_id" => &self.id,
-                "token_type" => token_type_string,
-            })?;
-        }
-
-        Ok(())
-    }
-
-    fn get_token(&self, token_type: OAuth2Tokentype) -> DalResult<Option<OAuth2Token>> {
-        let mut conn = self.mysql.get_conn()?;
-        let row: Row = match conn.exec_first("SELECT token,expiry FROM oauth2_tokens WHERE user_id = :user_id AND token_type = :token_type", params! {
-            "user_id" => &self.id,
-            "token_type" => token_type.get_token_type_string(),
-        })? {
-            Some(x) => x,
-            None => return Ok(None)
-        };
-
-        let token: String = row.get("token").unwrap();
-        let expiry: i64 = row.get("expiry").unwrap();
-
-        Ok(Some(OAuth2Token {
-            token,
-            expiry,
-            token_type,
-        }))
-    }
-}
\ No newline at end of file
+}
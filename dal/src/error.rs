@@ -8,11 +8,42 @@ pub type DalResult<T> = Result<T, Error>;
 #[derive(Debug, Error)]
 pub enum Error {
     /// A mysql error
+    #[cfg(feature = "mysql")]
     #[error("{0}")]
-    Mysql(#[from] mysql::Error),
+    Mysql(#[from] mysql_async::Error),
+    /// A postgres error
+    #[cfg(feature = "postgres")]
+    #[error("{0}")]
+    Postgres(#[from] tokio_postgres::Error),
+    /// Getting a postgres connection from the pool failed
+    #[cfg(feature = "postgres")]
+    #[error("{0}")]
+    PostgresPool(#[from] deadpool_postgres::PoolError),
+    /// Creating the postgres connection pool failed
+    #[cfg(feature = "postgres")]
+    #[error("{0}")]
+    PostgresCreatePool(#[from] deadpool_postgres::CreatePoolError),
+    /// A sqlite error
+    #[cfg(feature = "sqlite")]
+    #[error("{0}")]
+    Sqlite(#[from] rusqlite::Error),
+    /// A sqlite connection pool error
+    #[cfg(feature = "sqlite")]
+    #[error("{0}")]
+    SqlitePool(#[from] r2d2::Error),
+    /// A blocking sqlite task failed to complete
+    #[cfg(feature = "sqlite")]
+    #[error("{0}")]
+    Blocking(#[from] tokio::task::JoinError),
     /// A migration error
     #[error("{0}")]
     Refinery(#[from] refinery::Error),
+    /// The configured database requires a storage backend which was not enabled at compile time
+    #[error("The '{0}' storage backend is not enabled")]
+    BackendNotEnabled(&'static str),
+    /// Encrypting or decrypting a token failed
+    #[error("Encryption error: {0}")]
+    Encryption(String),
     /// The database is in an invalid state
     #[error("Invalid state: {0}")]
     InvalidState(String),
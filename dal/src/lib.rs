@@ -3,12 +3,18 @@ mod entity;
 use rand::Rng;
 pub use entity::*;
 
-mod dal_mysql;
-pub use dal_mysql::*;
+mod database;
+pub use database::*;
 
 mod error;
 pub use error::*;
 
+mod storage;
+pub use storage::{AuthorizationStartRecord, ConnectionGrantRecord, ConnectionRecord, LoginTicketRecord, OwnerRecord, Storage, StoredTokenRecord, TokenRecord, UserRecord};
+
+mod token_cipher;
+pub use token_cipher::*;
+
 fn generate_id(len: usize) -> String {
     rand::thread_rng().sample_iter(rand::distributions::Alphanumeric).take(len).map(char::from).collect()
-}
\ No newline at end of file
+}
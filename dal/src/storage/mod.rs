@@ -0,0 +1,258 @@
+use std::str::FromStr;
+use async_trait::async_trait;
+use crate::{AuditAction, AuditEvent, AuditEventFilter, AuditOutcome, DalResult, Error, ExactUser, NewAuditEvent, OAuth2Tokentype, OwnerKind, Region};
+
+#[cfg(feature = "mysql")]
+pub(crate) mod mysql;
+#[cfg(feature = "postgres")]
+pub(crate) mod postgres;
+#[cfg(feature = "sqlite")]
+pub(crate) mod sqlite;
+
+#[cfg(not(any(feature = "mysql", feature = "postgres", feature = "sqlite")))]
+compile_error!("At least one storage backend feature must be enabled: 'mysql', 'postgres' or 'sqlite'");
+
+/// A user as stored
+pub struct UserRecord {
+    pub id: String,
+}
+
+/// An Exact connection as stored
+#[derive(Clone)]
+pub struct ConnectionRecord {
+    pub id: String,
+    pub user_id: String,
+    pub owner_id: Option<String>,
+    pub label: Option<String>,
+    pub region: Region,
+    pub reauthorization_required: bool,
+    pub refresh_failures: u32,
+    pub refresh_retry_at: Option<i64>,
+    pub scopes: Option<String>,
+    pub last_refreshed_at: Option<i64>,
+}
+
+/// An owner of shared connections as stored
+#[derive(Clone)]
+pub struct OwnerRecord {
+    pub id: String,
+    pub name: String,
+    pub kind: OwnerKind,
+    pub created_at: i64,
+}
+
+/// A grant to use a shared connection as stored
+#[derive(Clone)]
+pub struct ConnectionGrantRecord {
+    pub connection_id: String,
+    pub grantee_id: String,
+    pub granted_by: String,
+    pub granted_at: i64,
+}
+
+/// An authorization start as stored
+#[derive(Clone)]
+pub struct AuthorizationStartRecord {
+    pub id: String,
+    pub user_id: String,
+    pub timestamp: i64,
+    pub caller: String,
+    pub exact_scopes: String,
+    pub region: Region,
+    pub connection_label: Option<String>,
+    pub connection_id: Option<String>,
+}
+
+/// A login ticket as stored
+#[derive(Clone)]
+pub struct LoginTicketRecord {
+    pub id: String,
+    pub user_id: String,
+    pub timestamp: i64,
+    pub caller: String,
+    pub exact_scopes: String,
+    pub region: Region,
+    pub connection_label: Option<String>,
+    pub connection_id: Option<String>,
+}
+
+/// A token of a connection as stored, i.e. encrypted
+#[derive(Clone)]
+pub struct TokenRecord {
+    pub token: String,
+    pub expiry: i64,
+}
+
+/// A token of any connection as stored, i.e. encrypted
+pub struct StoredTokenRecord {
+    pub connection_id: String,
+    pub token_type: OAuth2Tokentype,
+    pub token: String,
+}
+
+/// A storage backend.
+///
+/// Implementations only store and retrieve data, tokens are encrypted and decrypted
+/// by the entities before and after they pass through the storage backend.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// List all users
+    async fn list_users(&self) -> DalResult<Vec<UserRecord>>;
+
+    async fn get_user(&self, id: &str) -> DalResult<Option<UserRecord>>;
+
+    async fn create_user(&self, id: &str) -> DalResult<()>;
+
+    /// Create the personal connection. If the user already has a personal connection with the same label,
+    /// its region and scopes are updated instead. Returns the connection as stored
+    async fn create_connection(&self, connection: &ConnectionRecord) -> DalResult<ConnectionRecord>;
+
+    async fn get_connection(&self, id: &str) -> DalResult<Option<ConnectionRecord>>;
+
+    /// List all connections of the user which are not owned by an owner
+    async fn list_connections(&self, user_id: &str) -> DalResult<Vec<ConnectionRecord>>;
+
+    /// Find the connection of the user with the provided label, which is not owned by an owner.
+    /// A label of `None` matches the connection without a label
+    async fn get_connection_by_label(&self, user_id: &str, label: Option<&str>) -> DalResult<Option<ConnectionRecord>>;
+
+    async fn set_connection_region(&self, id: &str, region: Region) -> DalResult<()>;
+
+    async fn set_connection_scopes(&self, id: &str, scopes: &str) -> DalResult<()>;
+
+    async fn set_connection_last_refreshed_at(&self, id: &str, last_refreshed_at: i64) -> DalResult<()>;
+
+    /// Set or clear the owner of the connection
+    async fn set_connection_owner(&self, id: &str, owner_id: Option<&str>) -> DalResult<()>;
+
+    async fn create_owner(&self, owner: &OwnerRecord) -> DalResult<()>;
+
+    async fn get_owner(&self, id: &str) -> DalResult<Option<OwnerRecord>>;
+
+    /// List all owners, ordered by name
+    async fn list_owners(&self) -> DalResult<Vec<OwnerRecord>>;
+
+    /// List all connections owned by the owner
+    async fn list_owner_connections(&self, owner_id: &str) -> DalResult<Vec<ConnectionRecord>>;
+
+    /// Insert the grant, if the grantee was not granted the connection yet.
+    /// Returns whether the grant was inserted
+    async fn create_connection_grant(&self, grant: &ConnectionGrantRecord) -> DalResult<bool>;
+
+    /// Returns whether a grant was deleted
+    async fn delete_connection_grant(&self, connection_id: &str, grantee_id: &str) -> DalResult<bool>;
+
+    /// List all grants of the connection, ordered by grantee
+    async fn list_connection_grants(&self, connection_id: &str) -> DalResult<Vec<ConnectionGrantRecord>>;
+
+    async fn has_connection_grant(&self, connection_id: &str, grantee_id: &str) -> DalResult<bool>;
+
+    /// List all owned connections the grantee was granted
+    async fn list_granted_connections(&self, grantee_id: &str) -> DalResult<Vec<ConnectionRecord>>;
+
+    /// List all owned connections the user logged in to
+    async fn list_shared_connections_of_user(&self, user_id: &str) -> DalResult<Vec<ConnectionRecord>>;
+
+    /// Set the reauthorization requirement of the connection, clearing the refresh backoff
+    async fn set_reauthorization_required(&self, connection_id: &str) -> DalResult<()>;
+
+    /// Increment the number of refresh failures of the connection and set the refresh backoff
+    async fn record_refresh_failure(&self, connection_id: &str, retry_at: i64) -> DalResult<()>;
+
+    /// Clear the reauthorization requirement, refresh failures and refresh backoff of the connection
+    async fn reset_refresh_state(&self, connection_id: &str) -> DalResult<()>;
+
+    /// List all connections whose access token expires at or before `expires_before`, ordered by expiry.
+    /// Connections which must reauthorize, or which are backing off until after `now`, are excluded.
+    async fn list_refresh_due(&self, expires_before: i64, now: i64) -> DalResult<Vec<ConnectionRecord>>;
+
+    /// The minimum over all connections that do not require reauthorization of
+    /// `access token expiry - window_sec` and the refresh backoff, whichever comes last
+    async fn next_refresh_due(&self, window_sec: i64) -> DalResult<Option<i64>>;
+
+    /// Delete the connection with its tokens, Exact user, refresh lease and grants, and the logins its user did not complete,
+    /// in a single transaction
+    async fn delete_connection(&self, id: &str) -> DalResult<()>;
+
+    /// Delete the user with all its personal connections, grants, authorization starts and login tickets, in a single transaction.
+    /// In the same transaction, audit events of the user lose their IP address and user agent,
+    /// and the user's ID is replaced by `pseudonym` wherever it is the actor, subject or grantee.
+    /// Fails if the user logged in to a shared connection. Returns whether the user existed
+    async fn delete_user(&self, id: &str, pseudonym: &str) -> DalResult<bool>;
+
+    async fn create_authorization_start(&self, start: &AuthorizationStartRecord) -> DalResult<()>;
+
+    /// Retrieve and delete the authorization start atomically
+    async fn consume_authorization_start(&self, id: &str) -> DalResult<Option<AuthorizationStartRecord>>;
+
+    /// Returns the number of deleted authorization starts
+    async fn delete_authorization_starts_before(&self, timestamp: i64) -> DalResult<u64>;
+
+    async fn create_login_ticket(&self, ticket: &LoginTicketRecord) -> DalResult<()>;
+
+    /// Retrieve and delete the login ticket atomically
+    async fn consume_login_ticket(&self, id: &str) -> DalResult<Option<LoginTicketRecord>>;
+
+    /// Returns the number of deleted login tickets
+    async fn delete_login_tickets_before(&self, timestamp: i64) -> DalResult<u64>;
+
+    /// Insert or replace both tokens of the connection, in a single transaction
+    async fn set_token_pair(&self, connection_id: &str, access: &TokenRecord, refresh: &TokenRecord) -> DalResult<()>;
+
+    async fn get_token(&self, connection_id: &str, token_type: OAuth2Tokentype) -> DalResult<Option<TokenRecord>>;
+
+    /// List the tokens of all connections
+    async fn list_tokens(&self) -> DalResult<Vec<StoredTokenRecord>>;
+
+    /// Replace the token of the connection with `new`, only if it is still `current`.
+    /// Returns whether the token was replaced
+    async fn replace_token(&self, connection_id: &str, token_type: OAuth2Tokentype, current: &str, new: &str) -> DalResult<bool>;
+
+    /// Insert or replace the Exact user of the connection
+    async fn set_exact_user(&self, connection_id: &str, exact_user: &ExactUser) -> DalResult<()>;
+
+    async fn get_exact_user(&self, connection_id: &str) -> DalResult<Option<ExactUser>>;
+
+    /// Acquire the refresh lease of the connection for `holder` until `expires_at`,
+    /// if it is not held, held by `holder` or expired before `now`.
+    /// Returns whether the lease was acquired
+    async fn try_acquire_refresh_lease(&self, connection_id: &str, holder: &str, now: i64, expires_at: i64) -> DalResult<bool>;
+
+    /// Release the refresh lease of the connection, if it is held by `holder`
+    async fn release_refresh_lease(&self, connection_id: &str, holder: &str) -> DalResult<()>;
+
+    async fn create_audit_event(&self, timestamp: i64, event: &NewAuditEvent) -> DalResult<()>;
+
+    /// List at most `limit` audit events matching the filter, ordered by ID descending
+    async fn list_audit_events(&self, filter: &AuditEventFilter, limit: u32) -> DalResult<Vec<AuditEvent>>;
+}
+
+/// Parse a region as stored by a storage backend
+fn parse_region(region: &str) -> DalResult<Region> {
+    Region::from_str(region)
+        .map_err(|e| Error::InvalidState(e.to_string()))
+}
+
+/// Parse an owner kind as stored by a storage backend
+fn parse_owner_kind(kind: &str) -> DalResult<OwnerKind> {
+    OwnerKind::from_str(kind)
+        .map_err(|e| Error::InvalidState(e.to_string()))
+}
+
+/// Parse a token type as stored by a storage backend
+fn parse_token_type(token_type: &str) -> DalResult<OAuth2Tokentype> {
+    OAuth2Tokentype::from_token_type_string(token_type)
+        .ok_or_else(|| Error::InvalidState(format!("Unknown token type '{token_type}'")))
+}
+
+/// Parse an audit action as stored by a storage backend
+fn parse_audit_action(action: &str) -> DalResult<AuditAction> {
+    AuditAction::from_str(action)
+        .map_err(|e| Error::InvalidState(e.to_string()))
+}
+
+/// Parse an audit outcome as stored by a storage backend
+fn parse_audit_outcome(outcome: &str) -> DalResult<AuditOutcome> {
+    AuditOutcome::from_str(outcome)
+        .map_err(|e| Error::InvalidState(e.to_string()))
+}
@@ -0,0 +1,744 @@
+use async_trait::async_trait;
+use mysql_async::{OptsBuilder, params, Pool, Row, TxOpts};
+use mysql_async::prelude::Queryable;
+use crate::{AuditEvent, AuditEventFilter, DalResult, Error, ExactUser, NewAuditEvent, OAuth2Tokentype, Region};
+use crate::storage::{AuthorizationStartRecord, ConnectionGrantRecord, ConnectionRecord, LoginTicketRecord, OwnerRecord, parse_audit_action, parse_audit_outcome, parse_owner_kind, parse_region, parse_token_type, Storage, StoredTokenRecord, TokenRecord, UserRecord};
+
+/// MySQL or MariaDB storage backend
+pub struct MysqlStorage(Pool);
+
+impl MysqlStorage {
+    /// Connect to the database and apply migrations
+    ///
+    /// # Errors
+    ///
+    /// - If the supplied credentials are incorrect
+    /// - If the supplied host isn't reachable
+    /// - If the supplied database doesn't exist
+    /// - If creating the connection fails for any other reason ([See more](Pool::get_conn))
+    /// - If applying the migrations fails
+    pub async fn new(user: &str, password: &str, host: &str, database: &str) -> DalResult<Self> {
+        let opts = OptsBuilder::default()
+            .user(Some(user))
+            .pass(Some(password))
+            .ip_or_hostname(host)
+            .db_name(Some(database));
+        let mut pool = Pool::new(opts);
+
+        migrations::migrations::runner()
+            .set_migration_table_name("__mrauth_migrations")
+            .run_async(&mut pool)
+            .await?;
+
+        Ok(Self(pool))
+    }
+}
+
+/// Deletes a connection and everything referencing it, in order, with the logins its user did not complete.
+/// Every statement takes the connection ID
+const DELETE_CONNECTION_STATEMENTS: [&str; 7] = [
+    "DELETE FROM oauth2_authorization_start WHERE user_id = (SELECT user_id FROM connections WHERE id = :id)",
+    "DELETE FROM login_tickets WHERE user_id = (SELECT user_id FROM connections WHERE id = :id)",
+    "DELETE FROM oauth2_tokens WHERE connection_id = :id",
+    "DELETE FROM exact_users WHERE connection_id = :id",
+    "DELETE FROM refresh_leases WHERE connection_id = :id",
+    "DELETE FROM connection_grants WHERE connection_id = :id",
+    "DELETE FROM connections WHERE id = :id",
+];
+
+/// Deletes everything referencing a user, in order, before the user itself is deleted. Every statement takes the user ID.
+/// Shared connections are left alone, the user can not be deleted while it logged in to any
+const DELETE_USER_STATEMENTS: [&str; 7] = [
+    "DELETE FROM oauth2_tokens WHERE connection_id IN (SELECT id FROM connections WHERE user_id = :id AND owner_id IS NULL)",
+    "DELETE FROM exact_users WHERE connection_id IN (SELECT id FROM connections WHERE user_id = :id AND owner_id IS NULL)",
+    "DELETE FROM refresh_leases WHERE connection_id IN (SELECT id FROM connections WHERE user_id = :id AND owner_id IS NULL)",
+    "DELETE FROM connection_grants WHERE connection_id IN (SELECT id FROM connections WHERE user_id = :id AND owner_id IS NULL) OR grantee_id = :id",
+    "DELETE FROM connections WHERE user_id = :id AND owner_id IS NULL",
+    "DELETE FROM oauth2_authorization_start WHERE user_id = :id",
+    "DELETE FROM login_tickets WHERE user_id = :id",
+];
+
+const CONNECTION_COLUMNS: &str = "connections.id, connections.user_id, connections.owner_id, connections.label, connections.region, \
+    connections.reauthorization_required, connections.refresh_failures, connections.refresh_retry_at, \
+    connections.scopes, connections.last_refreshed_at";
+
+fn connection_from_row(row: Row) -> DalResult<ConnectionRecord> {
+    let region: String = row.get("region").unwrap();
+
+    Ok(ConnectionRecord {
+        id: row.get("id").unwrap(),
+        user_id: row.get("user_id").unwrap(),
+        owner_id: row.get("owner_id").unwrap(),
+        label: row.get("label").unwrap(),
+        region: parse_region(&region)?,
+        reauthorization_required: row.get("reauthorization_required").unwrap(),
+        refresh_failures: row.get("refresh_failures").unwrap(),
+        refresh_retry_at: row.get("refresh_retry_at").unwrap(),
+        scopes: row.get("scopes").unwrap(),
+        last_refreshed_at: row.get("last_refreshed_at").unwrap(),
+    })
+}
+
+fn owner_from_row(row: Row) -> DalResult<OwnerRecord> {
+    let kind: String = row.get("kind").unwrap();
+
+    Ok(OwnerRecord {
+        id: row.get("id").unwrap(),
+        name: row.get("name").unwrap(),
+        kind: parse_owner_kind(&kind)?,
+        created_at: row.get("created_at").unwrap(),
+    })
+}
+
+#[async_trait]
+impl Storage for MysqlStorage {
+    async fn list_users(&self) -> DalResult<Vec<UserRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let ids: Vec<String> = conn.query("SELECT id FROM users").await?;
+
+        Ok(ids.into_iter()
+            .map(|id| UserRecord { id })
+            .collect())
+    }
+
+    async fn get_user(&self, id: &str) -> DalResult<Option<UserRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let id: Option<String> = conn.exec_first("SELECT id FROM users WHERE id = :id", params! {
+            "id" => id
+        }).await?;
+
+        Ok(id.map(|id| UserRecord { id }))
+    }
+
+    async fn create_user(&self, id: &str) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("INSERT INTO users (id) VALUES (:id)", params! {
+            "id" => id
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn create_connection(&self, connection: &ConnectionRecord) -> DalResult<ConnectionRecord> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("INSERT INTO connections (id, user_id, owner_id, label, region, reauthorization_required, refresh_failures, refresh_retry_at, scopes, last_refreshed_at) \
+            VALUES (:id, :user_id, :owner_id, :label, :region, :reauthorization_required, :refresh_failures, :refresh_retry_at, :scopes, :last_refreshed_at) \
+            ON DUPLICATE KEY UPDATE region = VALUES(region), scopes = VALUES(scopes)", params! {
+            "id" => &connection.id,
+            "user_id" => &connection.user_id,
+            "owner_id" => &connection.owner_id,
+            "label" => &connection.label,
+            "region" => connection.region.as_str(),
+            "reauthorization_required" => connection.reauthorization_required,
+            "refresh_failures" => connection.refresh_failures,
+            "refresh_retry_at" => connection.refresh_retry_at,
+            "scopes" => &connection.scopes,
+            "last_refreshed_at" => connection.last_refreshed_at,
+        }).await?;
+
+        let row: Row = conn.exec_first(format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE user_id = :user_id AND label <=> :label AND owner_id IS NULL"), params! {
+            "user_id" => &connection.user_id,
+            "label" => &connection.label,
+        }).await?
+            .ok_or(Error::InvalidState("Connection was deleted while it was created".into()))?;
+
+        connection_from_row(row)
+    }
+
+    async fn get_connection(&self, id: &str) -> DalResult<Option<ConnectionRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let row: Row = match conn.exec_first(format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE id = :id"), params! {
+            "id" => id
+        }).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(connection_from_row(row)?))
+    }
+
+    async fn list_connections(&self, user_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let rows: Vec<Row> = conn.exec(format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE user_id = :user_id AND owner_id IS NULL ORDER BY id"), params! {
+            "user_id" => user_id
+        }).await?;
+
+        rows.into_iter()
+            .map(connection_from_row)
+            .collect()
+    }
+
+    async fn get_connection_by_label(&self, user_id: &str, label: Option<&str>) -> DalResult<Option<ConnectionRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        // `<=>` also matches if both sides are NULL
+        let row: Row = match conn.exec_first(format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE user_id = :user_id AND label <=> :label AND owner_id IS NULL"), params! {
+            "user_id" => user_id,
+            "label" => label,
+        }).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(connection_from_row(row)?))
+    }
+
+    async fn set_connection_region(&self, id: &str, region: Region) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("UPDATE connections SET region = :region WHERE id = :id", params! {
+            "region" => region.as_str(),
+            "id" => id,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn set_connection_scopes(&self, id: &str, scopes: &str) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("UPDATE connections SET scopes = :scopes WHERE id = :id", params! {
+            "scopes" => scopes,
+            "id" => id,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn set_connection_last_refreshed_at(&self, id: &str, last_refreshed_at: i64) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("UPDATE connections SET last_refreshed_at = :last_refreshed_at WHERE id = :id", params! {
+            "last_refreshed_at" => last_refreshed_at,
+            "id" => id,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn set_connection_owner(&self, id: &str, owner_id: Option<&str>) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("UPDATE connections SET owner_id = :owner_id WHERE id = :id", params! {
+            "owner_id" => owner_id,
+            "id" => id,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn create_owner(&self, owner: &OwnerRecord) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("INSERT INTO connection_owners (id, name, kind, created_at) VALUES (:id, :name, :kind, :created_at)", params! {
+            "id" => &owner.id,
+            "name" => &owner.name,
+            "kind" => owner.kind.as_str(),
+            "created_at" => owner.created_at,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn get_owner(&self, id: &str) -> DalResult<Option<OwnerRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let row: Row = match conn.exec_first("SELECT id, name, kind, created_at FROM connection_owners WHERE id = :id", params! {
+            "id" => id
+        }).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(owner_from_row(row)?))
+    }
+
+    async fn list_owners(&self) -> DalResult<Vec<OwnerRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let rows: Vec<Row> = conn.query("SELECT id, name, kind, created_at FROM connection_owners ORDER BY name").await?;
+
+        rows.into_iter()
+            .map(owner_from_row)
+            .collect()
+    }
+
+    async fn list_owner_connections(&self, owner_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let rows: Vec<Row> = conn.exec(format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE owner_id = :owner_id ORDER BY id"), params! {
+            "owner_id" => owner_id
+        }).await?;
+
+        rows.into_iter()
+            .map(connection_from_row)
+            .collect()
+    }
+
+    async fn create_connection_grant(&self, grant: &ConnectionGrantRecord) -> DalResult<bool> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("INSERT IGNORE INTO connection_grants (connection_id, grantee_id, granted_by, granted_at) \
+            VALUES (:connection_id, :grantee_id, :granted_by, :granted_at)", params! {
+            "connection_id" => &grant.connection_id,
+            "grantee_id" => &grant.grantee_id,
+            "granted_by" => &grant.granted_by,
+            "granted_at" => grant.granted_at,
+        }).await?;
+
+        Ok(conn.affected_rows() > 0)
+    }
+
+    async fn delete_connection_grant(&self, connection_id: &str, grantee_id: &str) -> DalResult<bool> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("DELETE FROM connection_grants WHERE connection_id = :connection_id AND grantee_id = :grantee_id", params! {
+            "connection_id" => connection_id,
+            "grantee_id" => grantee_id,
+        }).await?;
+
+        Ok(conn.affected_rows() > 0)
+    }
+
+    async fn list_connection_grants(&self, connection_id: &str) -> DalResult<Vec<ConnectionGrantRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let rows: Vec<Row> = conn.exec("SELECT connection_id, grantee_id, granted_by, granted_at FROM connection_grants \
+            WHERE connection_id = :connection_id ORDER BY grantee_id", params! {
+            "connection_id" => connection_id,
+        }).await?;
+
+        Ok(rows.into_iter()
+            .map(|row| ConnectionGrantRecord {
+                connection_id: row.get("connection_id").unwrap(),
+                grantee_id: row.get("grantee_id").unwrap(),
+                granted_by: row.get("granted_by").unwrap(),
+                granted_at: row.get("granted_at").unwrap(),
+            })
+            .collect())
+    }
+
+    async fn has_connection_grant(&self, connection_id: &str, grantee_id: &str) -> DalResult<bool> {
+        let mut conn = self.0.get_conn().await?;
+        let found: Option<String> = conn.exec_first("SELECT grantee_id FROM connection_grants WHERE connection_id = :connection_id AND grantee_id = :grantee_id", params! {
+            "connection_id" => connection_id,
+            "grantee_id" => grantee_id,
+        }).await?;
+
+        Ok(found.is_some())
+    }
+
+    async fn list_granted_connections(&self, grantee_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let rows: Vec<Row> = conn.exec(format!("SELECT {CONNECTION_COLUMNS} \
+            FROM connection_grants INNER JOIN connections ON connections.id = connection_grants.connection_id \
+            WHERE connection_grants.grantee_id = :grantee_id AND connections.owner_id IS NOT NULL \
+            ORDER BY connections.id"), params! {
+            "grantee_id" => grantee_id,
+        }).await?;
+
+        rows.into_iter()
+            .map(connection_from_row)
+            .collect()
+    }
+
+    async fn list_shared_connections_of_user(&self, user_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let rows: Vec<Row> = conn.exec(format!("SELECT {CONNECTION_COLUMNS} FROM connections \
+            WHERE user_id = :user_id AND owner_id IS NOT NULL ORDER BY id"), params! {
+            "user_id" => user_id,
+        }).await?;
+
+        rows.into_iter()
+            .map(connection_from_row)
+            .collect()
+    }
+
+    async fn set_reauthorization_required(&self, connection_id: &str) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("UPDATE connections SET reauthorization_required = TRUE, refresh_retry_at = NULL WHERE id = :id", params! {
+            "id" => connection_id,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn record_refresh_failure(&self, connection_id: &str, retry_at: i64) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("UPDATE connections SET refresh_failures = refresh_failures + 1, refresh_retry_at = :retry_at WHERE id = :id", params! {
+            "retry_at" => retry_at,
+            "id" => connection_id,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn reset_refresh_state(&self, connection_id: &str) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("UPDATE connections SET reauthorization_required = FALSE, refresh_failures = 0, refresh_retry_at = NULL WHERE id = :id", params! {
+            "id" => connection_id,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn list_refresh_due(&self, expires_before: i64, now: i64) -> DalResult<Vec<ConnectionRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let rows: Vec<Row> = conn.exec(format!("SELECT {CONNECTION_COLUMNS} \
+            FROM oauth2_tokens INNER JOIN connections ON connections.id = oauth2_tokens.connection_id \
+            WHERE oauth2_tokens.token_type = :token_type AND oauth2_tokens.expiry <= :expires_before \
+            AND connections.reauthorization_required = FALSE AND (connections.refresh_retry_at IS NULL OR connections.refresh_retry_at <= :now) \
+            ORDER BY oauth2_tokens.expiry"), params! {
+            "token_type" => OAuth2Tokentype::Access.get_token_type_string(),
+            "expires_before" => expires_before,
+            "now" => now,
+        }).await?;
+
+        rows.into_iter()
+            .map(connection_from_row)
+            .collect()
+    }
+
+    async fn next_refresh_due(&self, window_sec: i64) -> DalResult<Option<i64>> {
+        let mut conn = self.0.get_conn().await?;
+        let next_due: Option<Option<i64>> = conn.exec_first("SELECT MIN(GREATEST(oauth2_tokens.expiry - :window_sec, COALESCE(connections.refresh_retry_at, 0))) \
+            FROM oauth2_tokens INNER JOIN connections ON connections.id = oauth2_tokens.connection_id \
+            WHERE oauth2_tokens.token_type = :token_type AND connections.reauthorization_required = FALSE", params! {
+            "window_sec" => window_sec,
+            "token_type" => OAuth2Tokentype::Access.get_token_type_string(),
+        }).await?;
+
+        Ok(next_due.flatten())
+    }
+
+    async fn delete_connection(&self, id: &str) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        for statement in DELETE_CONNECTION_STATEMENTS {
+            tx.exec_drop(statement, params! {
+                "id" => id,
+            }).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: &str, pseudonym: &str) -> DalResult<bool> {
+        let mut conn = self.0.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        for statement in DELETE_USER_STATEMENTS {
+            tx.exec_drop(statement, params! {
+                "id" => id,
+            }).await?;
+        }
+
+        tx.exec_drop("UPDATE audit_events SET ip = NULL, user_agent = NULL WHERE user_id = :id OR actor = :id", params! {
+            "id" => id,
+        }).await?;
+        tx.exec_drop("UPDATE audit_events SET actor = :pseudonym WHERE actor = :id", params! {
+            "pseudonym" => pseudonym,
+            "id" => id,
+        }).await?;
+        tx.exec_drop("UPDATE audit_events SET user_id = :pseudonym WHERE user_id = :id", params! {
+            "pseudonym" => pseudonym,
+            "id" => id,
+        }).await?;
+        tx.exec_drop("UPDATE audit_events SET grantee_id = :pseudonym WHERE grantee_id = :id", params! {
+            "pseudonym" => pseudonym,
+            "id" => id,
+        }).await?;
+
+        tx.exec_drop("DELETE FROM users WHERE id = :id", params! {
+            "id" => id,
+        }).await?;
+        let existed = tx.affected_rows() > 0;
+        tx.commit().await?;
+
+        Ok(existed)
+    }
+
+    async fn create_authorization_start(&self, start: &AuthorizationStartRecord) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("INSERT INTO oauth2_authorization_start (id, user_id, timestamp, caller, scopes, region, connection_label, connection_id) \
+            VALUES (:id, :user_id, :timestamp, :caller, :scopes, :region, :connection_label, :connection_id)", params! {
+            "id" => &start.id,
+            "user_id" => &start.user_id,
+            "timestamp" => start.timestamp,
+            "caller" => &start.caller,
+            "scopes" => &start.exact_scopes,
+            "region" => start.region.as_str(),
+            "connection_label" => &start.connection_label,
+            "connection_id" => &start.connection_id,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn consume_authorization_start(&self, id: &str) -> DalResult<Option<AuthorizationStartRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        let row: Row = match tx.exec_first("SELECT user_id, timestamp, caller, scopes, region, connection_label, connection_id FROM oauth2_authorization_start WHERE id = :id FOR UPDATE", params! {
+            "id" => id
+        }).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        tx.exec_drop("DELETE FROM oauth2_authorization_start WHERE id = :id", params! {
+            "id" => id
+        }).await?;
+        tx.commit().await?;
+
+        let region: String = row.get("region").unwrap();
+        Ok(Some(AuthorizationStartRecord {
+            id: id.to_string(),
+            user_id: row.get("user_id").unwrap(),
+            timestamp: row.get("timestamp").unwrap(),
+            caller: row.get("caller").unwrap(),
+            exact_scopes: row.get("scopes").unwrap(),
+            region: parse_region(&region)?,
+            connection_label: row.get("connection_label").unwrap(),
+            connection_id: row.get("connection_id").unwrap(),
+        }))
+    }
+
+    async fn delete_authorization_starts_before(&self, timestamp: i64) -> DalResult<u64> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("DELETE FROM oauth2_authorization_start WHERE timestamp < :timestamp", params! {
+            "timestamp" => timestamp
+        }).await?;
+
+        Ok(conn.affected_rows())
+    }
+
+    async fn create_login_ticket(&self, ticket: &LoginTicketRecord) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("INSERT INTO login_tickets (id, user_id, timestamp, caller, scopes, region, connection_label, connection_id) \
+            VALUES (:id, :user_id, :timestamp, :caller, :scopes, :region, :connection_label, :connection_id)", params! {
+            "id" => &ticket.id,
+            "user_id" => &ticket.user_id,
+            "timestamp" => ticket.timestamp,
+            "caller" => &ticket.caller,
+            "scopes" => &ticket.exact_scopes,
+            "region" => ticket.region.as_str(),
+            "connection_label" => &ticket.connection_label,
+            "connection_id" => &ticket.connection_id,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn consume_login_ticket(&self, id: &str) -> DalResult<Option<LoginTicketRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        let row: Row = match tx.exec_first("SELECT user_id, timestamp, caller, scopes, region, connection_label, connection_id FROM login_tickets WHERE id = :id FOR UPDATE", params! {
+            "id" => id
+        }).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        tx.exec_drop("DELETE FROM login_tickets WHERE id = :id", params! {
+            "id" => id
+        }).await?;
+        tx.commit().await?;
+
+        let region: String = row.get("region").unwrap();
+        Ok(Some(LoginTicketRecord {
+            id: id.to_string(),
+            user_id: row.get("user_id").unwrap(),
+            timestamp: row.get("timestamp").unwrap(),
+            caller: row.get("caller").unwrap(),
+            exact_scopes: row.get("scopes").unwrap(),
+            region: parse_region(&region)?,
+            connection_label: row.get("connection_label").unwrap(),
+            connection_id: row.get("connection_id").unwrap(),
+        }))
+    }
+
+    async fn delete_login_tickets_before(&self, timestamp: i64) -> DalResult<u64> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("DELETE FROM login_tickets WHERE timestamp < :timestamp", params! {
+            "timestamp" => timestamp
+        }).await?;
+
+        Ok(conn.affected_rows())
+    }
+
+    async fn set_token_pair(&self, connection_id: &str, access: &TokenRecord, refresh: &TokenRecord) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        let mut tx = conn.start_transaction(TxOpts::default()).await?;
+        for (token_type, record) in [(OAuth2Tokentype::Access, access), (OAuth2Tokentype::Refresh, refresh)] {
+            tx.exec_drop("INSERT INTO oauth2_tokens (connection_id, token, token_type, expiry) VALUES (:connection_id, :token, :token_type, :expiry) \
+                ON DUPLICATE KEY UPDATE token = VALUES(token), expiry = VALUES(expiry)", params! {
+                "connection_id" => connection_id,
+                "token" => &record.token,
+                "token_type" => token_type.get_token_type_string(),
+                "expiry" => record.expiry,
+            }).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_token(&self, connection_id: &str, token_type: OAuth2Tokentype) -> DalResult<Option<TokenRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let row: Row = match conn.exec_first("SELECT token, expiry FROM oauth2_tokens WHERE connection_id = :connection_id AND token_type = :token_type", params! {
+            "connection_id" => connection_id,
+            "token_type" => token_type.get_token_type_string(),
+        }).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(TokenRecord {
+            token: row.get("token").unwrap(),
+            expiry: row.get("expiry").unwrap(),
+        }))
+    }
+
+    async fn list_tokens(&self) -> DalResult<Vec<StoredTokenRecord>> {
+        let mut conn = self.0.get_conn().await?;
+        let rows: Vec<Row> = conn.query("SELECT connection_id, token_type, token FROM oauth2_tokens").await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let token_type: String = row.get("token_type").unwrap();
+                Ok(StoredTokenRecord {
+                    connection_id: row.get("connection_id").unwrap(),
+                    token_type: parse_token_type(&token_type)?,
+                    token: row.get("token").unwrap(),
+                })
+            })
+            .collect()
+    }
+
+    async fn replace_token(&self, connection_id: &str, token_type: OAuth2Tokentype, current: &str, new: &str) -> DalResult<bool> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("UPDATE oauth2_tokens SET token = :new WHERE connection_id = :connection_id AND token_type = :token_type AND token = :current", params! {
+            "new" => new,
+            "connection_id" => connection_id,
+            "token_type" => token_type.get_token_type_string(),
+            "current" => current,
+        }).await?;
+
+        Ok(conn.affected_rows() > 0)
+    }
+
+    async fn set_exact_user(&self, connection_id: &str, exact_user: &ExactUser) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("INSERT INTO exact_users (connection_id, exact_user_id, full_name, email, current_division) VALUES (:connection_id, :exact_user_id, :full_name, :email, :current_division) \
+            ON DUPLICATE KEY UPDATE exact_user_id = VALUES(exact_user_id), full_name = VALUES(full_name), email = VALUES(email), current_division = VALUES(current_division)", params! {
+            "connection_id" => connection_id,
+            "exact_user_id" => &exact_user.exact_user_id,
+            "full_name" => &exact_user.full_name,
+            "email" => &exact_user.email,
+            "current_division" => exact_user.current_division,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn get_exact_user(&self, connection_id: &str) -> DalResult<Option<ExactUser>> {
+        let mut conn = self.0.get_conn().await?;
+        let row: Row = match conn.exec_first("SELECT exact_user_id, full_name, email, current_division FROM exact_users WHERE connection_id = :connection_id", params! {
+            "connection_id" => connection_id,
+        }).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(ExactUser {
+            exact_user_id: row.get("exact_user_id").unwrap(),
+            full_name: row.get("full_name").unwrap(),
+            email: row.get("email").unwrap(),
+            current_division: row.get("current_division").unwrap(),
+        }))
+    }
+
+    async fn try_acquire_refresh_lease(&self, connection_id: &str, holder: &str, now: i64, expires_at: i64) -> DalResult<bool> {
+        let mut conn = self.0.get_conn().await?;
+        // The assignments are evaluated in order, the second uses the holder as set by the first
+        conn.exec_drop("INSERT INTO refresh_leases (connection_id, holder, expires_at) VALUES (:connection_id, :holder, :expires_at) \
+            ON DUPLICATE KEY UPDATE holder = IF(expires_at < :now, VALUES(holder), holder), \
+            expires_at = IF(holder = VALUES(holder), VALUES(expires_at), expires_at)", params! {
+            "connection_id" => connection_id,
+            "holder" => holder,
+            "expires_at" => expires_at,
+            "now" => now,
+        }).await?;
+
+        let current_holder: Option<String> = conn.exec_first("SELECT holder FROM refresh_leases WHERE connection_id = :connection_id", params! {
+            "connection_id" => connection_id,
+        }).await?;
+
+        Ok(current_holder.as_deref() == Some(holder))
+    }
+
+    async fn release_refresh_lease(&self, connection_id: &str, holder: &str) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("DELETE FROM refresh_leases WHERE connection_id = :connection_id AND holder = :holder", params! {
+            "connection_id" => connection_id,
+            "holder" => holder,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn create_audit_event(&self, timestamp: i64, event: &NewAuditEvent) -> DalResult<()> {
+        let mut conn = self.0.get_conn().await?;
+        conn.exec_drop("INSERT INTO audit_events (timestamp, actor, user_id, connection_id, grantee_id, action, outcome, detail, ip, user_agent) \
+            VALUES (:timestamp, :actor, :user_id, :connection_id, :grantee_id, :action, :outcome, :detail, :ip, :user_agent)", params! {
+            "timestamp" => timestamp,
+            "actor" => &event.actor,
+            "user_id" => &event.user_id,
+            "connection_id" => &event.connection_id,
+            "grantee_id" => &event.grantee_id,
+            "action" => event.action.as_str(),
+            "outcome" => event.outcome.as_str(),
+            "detail" => &event.detail,
+            "ip" => &event.ip,
+            "user_agent" => &event.user_agent,
+        }).await?;
+
+        Ok(())
+    }
+
+    async fn list_audit_events(&self, filter: &AuditEventFilter, limit: u32) -> DalResult<Vec<AuditEvent>> {
+        let mut conn = self.0.get_conn().await?;
+        let rows: Vec<Row> = conn.exec("SELECT id, timestamp, actor, user_id, connection_id, grantee_id, action, outcome, detail, ip, user_agent FROM audit_events \
+            WHERE (:actor IS NULL OR actor = :actor) \
+            AND (:user_id IS NULL OR user_id = :user_id) \
+            AND (:action IS NULL OR action = :action) \
+            AND (:outcome IS NULL OR outcome = :outcome) \
+            AND (:from IS NULL OR timestamp >= :from) \
+            AND (:to IS NULL OR timestamp <= :to) \
+            AND (:before_id IS NULL OR id < :before_id) \
+            ORDER BY id DESC LIMIT :limit", params! {
+            "actor" => &filter.actor,
+            "user_id" => &filter.user_id,
+            "action" => filter.action.map(|action| action.as_str()),
+            "outcome" => filter.outcome.map(|outcome| outcome.as_str()),
+            "from" => filter.from,
+            "to" => filter.to,
+            "before_id" => filter.before_id,
+            "limit" => limit,
+        }).await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let action: String = row.get("action").unwrap();
+                let outcome: String = row.get("outcome").unwrap();
+                Ok(AuditEvent {
+                    id: row.get("id").unwrap(),
+                    timestamp: row.get("timestamp").unwrap(),
+                    actor: row.get("actor").unwrap(),
+                    user_id: row.get("user_id").unwrap(),
+                    connection_id: row.get("connection_id").unwrap(),
+                    grantee_id: row.get("grantee_id").unwrap(),
+                    action: parse_audit_action(&action)?,
+                    outcome: parse_audit_outcome(&outcome)?,
+                    detail: row.get("detail").unwrap(),
+                    ip: row.get("ip").unwrap(),
+                    user_agent: row.get("user_agent").unwrap(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Embedded migrations
+mod migrations {
+    use refinery::embed_migrations;
+    embed_migrations!("./migrations/mysql");
+}
@@ -0,0 +1,576 @@
+use async_trait::async_trait;
+use deadpool_postgres::{Client, Pool, Runtime};
+use tokio_postgres::{NoTls, Row};
+use crate::{AuditEvent, AuditEventFilter, DalResult, ExactUser, NewAuditEvent, OAuth2Tokentype, Region};
+use crate::storage::{AuthorizationStartRecord, ConnectionGrantRecord, ConnectionRecord, LoginTicketRecord, OwnerRecord, parse_audit_action, parse_audit_outcome, parse_owner_kind, parse_region, parse_token_type, Storage, StoredTokenRecord, TokenRecord, UserRecord};
+
+/// PostgreSQL storage backend
+pub struct PostgresStorage(Pool);
+
+impl PostgresStorage {
+    /// Connect to the database and apply migrations
+    ///
+    /// # Errors
+    ///
+    /// - If the supplied credentials are incorrect
+    /// - If the supplied host isn't reachable
+    /// - If the supplied database doesn't exist
+    /// - If applying the migrations fails
+    pub async fn new(user: &str, password: &str, host: &str, database: &str) -> DalResult<Self> {
+        let config = deadpool_postgres::Config {
+            user: Some(user.to_string()),
+            password: Some(password.to_string()),
+            host: Some(host.to_string()),
+            dbname: Some(database.to_string()),
+            ..Default::default()
+        };
+        let pool = config.create_pool(Some(Runtime::Tokio1), NoTls)?;
+
+        let mut client = pool.get().await?;
+        migrations::migrations::runner()
+            .set_migration_table_name("__mrauth_migrations")
+            .run_async(&mut **client)
+            .await?;
+
+        Ok(Self(pool))
+    }
+
+    async fn conn(&self) -> DalResult<Client> {
+        Ok(self.0.get().await?)
+    }
+}
+
+/// Deletes a connection and everything referencing it, in order, with the logins its user did not complete.
+/// Every statement takes the connection ID
+const DELETE_CONNECTION_STATEMENTS: [&str; 7] = [
+    "DELETE FROM oauth2_authorization_start WHERE user_id = (SELECT user_id FROM connections WHERE id = $1)",
+    "DELETE FROM login_tickets WHERE user_id = (SELECT user_id FROM connections WHERE id = $1)",
+    "DELETE FROM oauth2_tokens WHERE connection_id = $1",
+    "DELETE FROM exact_users WHERE connection_id = $1",
+    "DELETE FROM refresh_leases WHERE connection_id = $1",
+    "DELETE FROM connection_grants WHERE connection_id = $1",
+    "DELETE FROM connections WHERE id = $1",
+];
+
+/// Deletes everything referencing a user, in order, before the user itself is deleted. Every statement takes the user ID.
+/// Shared connections are left alone, the user can not be deleted while it logged in to any
+const DELETE_USER_STATEMENTS: [&str; 7] = [
+    "DELETE FROM oauth2_tokens WHERE connection_id IN (SELECT id FROM connections WHERE user_id = $1 AND owner_id IS NULL)",
+    "DELETE FROM exact_users WHERE connection_id IN (SELECT id FROM connections WHERE user_id = $1 AND owner_id IS NULL)",
+    "DELETE FROM refresh_leases WHERE connection_id IN (SELECT id FROM connections WHERE user_id = $1 AND owner_id IS NULL)",
+    "DELETE FROM connection_grants WHERE connection_id IN (SELECT id FROM connections WHERE user_id = $1 AND owner_id IS NULL) OR grantee_id = $1",
+    "DELETE FROM connections WHERE user_id = $1 AND owner_id IS NULL",
+    "DELETE FROM oauth2_authorization_start WHERE user_id = $1",
+    "DELETE FROM login_tickets WHERE user_id = $1",
+];
+
+const CONNECTION_COLUMNS: &str = "connections.id, connections.user_id, connections.owner_id, connections.label, connections.region, \
+    connections.reauthorization_required, connections.refresh_failures, connections.refresh_retry_at, \
+    connections.scopes, connections.last_refreshed_at";
+
+fn connection_from_row(row: &Row) -> DalResult<ConnectionRecord> {
+    let refresh_failures: i32 = row.get("refresh_failures");
+
+    Ok(ConnectionRecord {
+        id: row.get("id"),
+        user_id: row.get("user_id"),
+        owner_id: row.get("owner_id"),
+        label: row.get("label"),
+        region: parse_region(row.get("region"))?,
+        reauthorization_required: row.get("reauthorization_required"),
+        refresh_failures: refresh_failures as u32,
+        refresh_retry_at: row.get("refresh_retry_at"),
+        scopes: row.get("scopes"),
+        last_refreshed_at: row.get("last_refreshed_at"),
+    })
+}
+
+fn owner_from_row(row: &Row) -> DalResult<OwnerRecord> {
+    Ok(OwnerRecord {
+        id: row.get("id"),
+        name: row.get("name"),
+        kind: parse_owner_kind(row.get("kind"))?,
+        created_at: row.get("created_at"),
+    })
+}
+
+fn connection_grant_from_row(row: &Row) -> ConnectionGrantRecord {
+    ConnectionGrantRecord {
+        connection_id: row.get("connection_id"),
+        grantee_id: row.get("grantee_id"),
+        granted_by: row.get("granted_by"),
+        granted_at: row.get("granted_at"),
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    async fn list_users(&self) -> DalResult<Vec<UserRecord>> {
+        let rows = self.conn().await?.query("SELECT id FROM users", &[]).await?;
+
+        Ok(rows.iter()
+            .map(|row| UserRecord { id: row.get("id") })
+            .collect())
+    }
+
+    async fn get_user(&self, id: &str) -> DalResult<Option<UserRecord>> {
+        let row = self.conn().await?.query_opt("SELECT id FROM users WHERE id = $1", &[&id]).await?;
+        Ok(row.map(|row| UserRecord { id: row.get("id") }))
+    }
+
+    async fn create_user(&self, id: &str) -> DalResult<()> {
+        self.conn().await?.execute("INSERT INTO users (id) VALUES ($1)", &[&id]).await?;
+        Ok(())
+    }
+
+    async fn create_connection(&self, connection: &ConnectionRecord) -> DalResult<ConnectionRecord> {
+        let row = self.conn().await?.query_one(&format!("INSERT INTO connections (id, user_id, owner_id, label, region, reauthorization_required, refresh_failures, refresh_retry_at, scopes, last_refreshed_at) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) \
+            ON CONFLICT (user_id, COALESCE(label, '')) WHERE owner_id IS NULL DO UPDATE SET region = EXCLUDED.region, scopes = EXCLUDED.scopes \
+            RETURNING {CONNECTION_COLUMNS}"), &[
+            &connection.id,
+            &connection.user_id,
+            &connection.owner_id,
+            &connection.label,
+            &connection.region.as_str(),
+            &connection.reauthorization_required,
+            &(connection.refresh_failures as i32),
+            &connection.refresh_retry_at,
+            &connection.scopes,
+            &connection.last_refreshed_at,
+        ]).await?;
+
+        connection_from_row(&row)
+    }
+
+    async fn get_connection(&self, id: &str) -> DalResult<Option<ConnectionRecord>> {
+        let row = match self.conn().await?.query_opt(&format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE id = $1"), &[&id]).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(connection_from_row(&row)?))
+    }
+
+    async fn list_connections(&self, user_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let rows = self.conn().await?.query(&format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE user_id = $1 AND owner_id IS NULL ORDER BY id"), &[&user_id]).await?;
+
+        rows.iter()
+            .map(connection_from_row)
+            .collect()
+    }
+
+    async fn get_connection_by_label(&self, user_id: &str, label: Option<&str>) -> DalResult<Option<ConnectionRecord>> {
+        let row = match self.conn().await?.query_opt(&format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE user_id = $1 AND label IS NOT DISTINCT FROM $2 AND owner_id IS NULL"), &[&user_id, &label]).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(connection_from_row(&row)?))
+    }
+
+    async fn set_connection_region(&self, id: &str, region: Region) -> DalResult<()> {
+        self.conn().await?.execute("UPDATE connections SET region = $1 WHERE id = $2", &[&region.as_str(), &id]).await?;
+        Ok(())
+    }
+
+    async fn set_connection_scopes(&self, id: &str, scopes: &str) -> DalResult<()> {
+        self.conn().await?.execute("UPDATE connections SET scopes = $1 WHERE id = $2", &[&scopes, &id]).await?;
+        Ok(())
+    }
+
+    async fn set_connection_last_refreshed_at(&self, id: &str, last_refreshed_at: i64) -> DalResult<()> {
+        self.conn().await?.execute("UPDATE connections SET last_refreshed_at = $1 WHERE id = $2", &[&last_refreshed_at, &id]).await?;
+        Ok(())
+    }
+
+    async fn set_connection_owner(&self, id: &str, owner_id: Option<&str>) -> DalResult<()> {
+        self.conn().await?.execute("UPDATE connections SET owner_id = $1 WHERE id = $2", &[&owner_id, &id]).await?;
+        Ok(())
+    }
+
+    async fn create_owner(&self, owner: &OwnerRecord) -> DalResult<()> {
+        self.conn().await?.execute("INSERT INTO connection_owners (id, name, kind, created_at) VALUES ($1, $2, $3, $4)", &[
+            &owner.id,
+            &owner.name,
+            &owner.kind.as_str(),
+            &owner.created_at,
+        ]).await?;
+
+        Ok(())
+    }
+
+    async fn get_owner(&self, id: &str) -> DalResult<Option<OwnerRecord>> {
+        let row = match self.conn().await?.query_opt("SELECT id, name, kind, created_at FROM connection_owners WHERE id = $1", &[&id]).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(owner_from_row(&row)?))
+    }
+
+    async fn list_owners(&self) -> DalResult<Vec<OwnerRecord>> {
+        let rows = self.conn().await?.query("SELECT id, name, kind, created_at FROM connection_owners ORDER BY name", &[]).await?;
+
+        rows.iter()
+            .map(owner_from_row)
+            .collect()
+    }
+
+    async fn list_owner_connections(&self, owner_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let rows = self.conn().await?.query(&format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE owner_id = $1 ORDER BY id"), &[&owner_id]).await?;
+
+        rows.iter()
+            .map(connection_from_row)
+            .collect()
+    }
+
+    async fn create_connection_grant(&self, grant: &ConnectionGrantRecord) -> DalResult<bool> {
+        let inserted = self.conn().await?.execute("INSERT INTO connection_grants (connection_id, grantee_id, granted_by, granted_at) \
+            VALUES ($1, $2, $3, $4) ON CONFLICT (connection_id, grantee_id) DO NOTHING", &[
+            &grant.connection_id,
+            &grant.grantee_id,
+            &grant.granted_by,
+            &grant.granted_at,
+        ]).await?;
+
+        Ok(inserted > 0)
+    }
+
+    async fn delete_connection_grant(&self, connection_id: &str, grantee_id: &str) -> DalResult<bool> {
+        let deleted = self.conn().await?.execute("DELETE FROM connection_grants WHERE connection_id = $1 AND grantee_id = $2", &[&connection_id, &grantee_id]).await?;
+        Ok(deleted > 0)
+    }
+
+    async fn list_connection_grants(&self, connection_id: &str) -> DalResult<Vec<ConnectionGrantRecord>> {
+        let rows = self.conn().await?.query("SELECT connection_id, grantee_id, granted_by, granted_at FROM connection_grants \
+            WHERE connection_id = $1 ORDER BY grantee_id", &[&connection_id]).await?;
+
+        Ok(rows.iter()
+            .map(connection_grant_from_row)
+            .collect())
+    }
+
+    async fn has_connection_grant(&self, connection_id: &str, grantee_id: &str) -> DalResult<bool> {
+        let row = self.conn().await?.query_opt("SELECT grantee_id FROM connection_grants WHERE connection_id = $1 AND grantee_id = $2", &[&connection_id, &grantee_id]).await?;
+        Ok(row.is_some())
+    }
+
+    async fn list_granted_connections(&self, grantee_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let rows = self.conn().await?.query(&format!("SELECT {CONNECTION_COLUMNS} \
+            FROM connection_grants INNER JOIN connections ON connections.id = connection_grants.connection_id \
+            WHERE connection_grants.grantee_id = $1 AND connections.owner_id IS NOT NULL \
+            ORDER BY connections.id"), &[&grantee_id]).await?;
+
+        rows.iter()
+            .map(connection_from_row)
+            .collect()
+    }
+
+    async fn list_shared_connections_of_user(&self, user_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let rows = self.conn().await?.query(&format!("SELECT {CONNECTION_COLUMNS} FROM connections \
+            WHERE user_id = $1 AND owner_id IS NOT NULL ORDER BY id"), &[&user_id]).await?;
+
+        rows.iter()
+            .map(connection_from_row)
+            .collect()
+    }
+
+    async fn set_reauthorization_required(&self, connection_id: &str) -> DalResult<()> {
+        self.conn().await?.execute("UPDATE connections SET reauthorization_required = TRUE, refresh_retry_at = NULL WHERE id = $1", &[&connection_id]).await?;
+        Ok(())
+    }
+
+    async fn record_refresh_failure(&self, connection_id: &str, retry_at: i64) -> DalResult<()> {
+        self.conn().await?.execute("UPDATE connections SET refresh_failures = refresh_failures + 1, refresh_retry_at = $1 WHERE id = $2", &[&retry_at, &connection_id]).await?;
+        Ok(())
+    }
+
+    async fn reset_refresh_state(&self, connection_id: &str) -> DalResult<()> {
+        self.conn().await?.execute("UPDATE connections SET reauthorization_required = FALSE, refresh_failures = 0, refresh_retry_at = NULL WHERE id = $1", &[&connection_id]).await?;
+        Ok(())
+    }
+
+    async fn list_refresh_due(&self, expires_before: i64, now: i64) -> DalResult<Vec<ConnectionRecord>> {
+        let rows = self.conn().await?.query(&format!("SELECT {CONNECTION_COLUMNS} \
+            FROM oauth2_tokens INNER JOIN connections ON connections.id = oauth2_tokens.connection_id \
+            WHERE oauth2_tokens.token_type = $1 AND oauth2_tokens.expiry <= $2 \
+            AND connections.reauthorization_required = FALSE AND (connections.refresh_retry_at IS NULL OR connections.refresh_retry_at <= $3) \
+            ORDER BY oauth2_tokens.expiry"), &[&OAuth2Tokentype::Access.get_token_type_string(), &expires_before, &now]).await?;
+
+        rows.iter()
+            .map(connection_from_row)
+            .collect()
+    }
+
+    async fn next_refresh_due(&self, window_sec: i64) -> DalResult<Option<i64>> {
+        let row = self.conn().await?.query_one("SELECT MIN(GREATEST(oauth2_tokens.expiry - $1, COALESCE(connections.refresh_retry_at, 0))) \
+            FROM oauth2_tokens INNER JOIN connections ON connections.id = oauth2_tokens.connection_id \
+            WHERE oauth2_tokens.token_type = $2 AND connections.reauthorization_required = FALSE", &[&window_sec, &OAuth2Tokentype::Access.get_token_type_string()]).await?;
+
+        Ok(row.get(0))
+    }
+
+    async fn delete_connection(&self, id: &str) -> DalResult<()> {
+        let mut conn = self.conn().await?;
+        let tx = conn.transaction().await?;
+        for statement in DELETE_CONNECTION_STATEMENTS {
+            tx.execute(statement, &[&id]).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_user(&self, id: &str, pseudonym: &str) -> DalResult<bool> {
+        let mut conn = self.conn().await?;
+        let tx = conn.transaction().await?;
+        for statement in DELETE_USER_STATEMENTS {
+            tx.execute(statement, &[&id]).await?;
+        }
+
+        tx.execute("UPDATE audit_events SET ip = NULL, user_agent = NULL WHERE user_id = $1 OR actor = $1", &[&id]).await?;
+        tx.execute("UPDATE audit_events SET actor = $1 WHERE actor = $2", &[&pseudonym, &id]).await?;
+        tx.execute("UPDATE audit_events SET user_id = $1 WHERE user_id = $2", &[&pseudonym, &id]).await?;
+        tx.execute("UPDATE audit_events SET grantee_id = $1 WHERE grantee_id = $2", &[&pseudonym, &id]).await?;
+
+        let deleted = tx.execute("DELETE FROM users WHERE id = $1", &[&id]).await?;
+        tx.commit().await?;
+
+        Ok(deleted > 0)
+    }
+
+    async fn create_authorization_start(&self, start: &AuthorizationStartRecord) -> DalResult<()> {
+        self.conn().await?.execute("INSERT INTO oauth2_authorization_start (id, user_id, timestamp, caller, scopes, region, connection_label, connection_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)", &[
+            &start.id,
+            &start.user_id,
+            &start.timestamp,
+            &start.caller,
+            &start.exact_scopes,
+            &start.region.as_str(),
+            &start.connection_label,
+            &start.connection_id,
+        ]).await?;
+
+        Ok(())
+    }
+
+    async fn consume_authorization_start(&self, id: &str) -> DalResult<Option<AuthorizationStartRecord>> {
+        let row = match self.conn().await?.query_opt("DELETE FROM oauth2_authorization_start WHERE id = $1 RETURNING user_id, timestamp, caller, scopes, region, connection_label, connection_id", &[&id]).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(AuthorizationStartRecord {
+            id: id.to_string(),
+            user_id: row.get("user_id"),
+            timestamp: row.get("timestamp"),
+            caller: row.get("caller"),
+            exact_scopes: row.get("scopes"),
+            region: parse_region(row.get("region"))?,
+            connection_label: row.get("connection_label"),
+            connection_id: row.get("connection_id"),
+        }))
+    }
+
+    async fn delete_authorization_starts_before(&self, timestamp: i64) -> DalResult<u64> {
+        Ok(self.conn().await?.execute("DELETE FROM oauth2_authorization_start WHERE timestamp < $1", &[&timestamp]).await?)
+    }
+
+    async fn create_login_ticket(&self, ticket: &LoginTicketRecord) -> DalResult<()> {
+        self.conn().await?.execute("INSERT INTO login_tickets (id, user_id, timestamp, caller, scopes, region, connection_label, connection_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)", &[
+            &ticket.id,
+            &ticket.user_id,
+            &ticket.timestamp,
+            &ticket.caller,
+            &ticket.exact_scopes,
+            &ticket.region.as_str(),
+            &ticket.connection_label,
+            &ticket.connection_id,
+        ]).await?;
+
+        Ok(())
+    }
+
+    async fn consume_login_ticket(&self, id: &str) -> DalResult<Option<LoginTicketRecord>> {
+        let row = match self.conn().await?.query_opt("DELETE FROM login_tickets WHERE id = $1 RETURNING user_id, timestamp, caller, scopes, region, connection_label, connection_id", &[&id]).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(LoginTicketRecord {
+            id: id.to_string(),
+            user_id: row.get("user_id"),
+            timestamp: row.get("timestamp"),
+            caller: row.get("caller"),
+            exact_scopes: row.get("scopes"),
+            region: parse_region(row.get("region"))?,
+            connection_label: row.get("connection_label"),
+            connection_id: row.get("connection_id"),
+        }))
+    }
+
+    async fn delete_login_tickets_before(&self, timestamp: i64) -> DalResult<u64> {
+        Ok(self.conn().await?.execute("DELETE FROM login_tickets WHERE timestamp < $1", &[&timestamp]).await?)
+    }
+
+    async fn set_token_pair(&self, connection_id: &str, access: &TokenRecord, refresh: &TokenRecord) -> DalResult<()> {
+        let mut conn = self.conn().await?;
+        let tx = conn.transaction().await?;
+        for (token_type, record) in [(OAuth2Tokentype::Access, access), (OAuth2Tokentype::Refresh, refresh)] {
+            tx.execute("INSERT INTO oauth2_tokens (connection_id, token, token_type, expiry) VALUES ($1, $2, $3, $4) \
+                ON CONFLICT (connection_id, token_type) DO UPDATE SET token = EXCLUDED.token, expiry = EXCLUDED.expiry", &[
+                &connection_id,
+                &record.token,
+                &token_type.get_token_type_string(),
+                &record.expiry,
+            ]).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn get_token(&self, connection_id: &str, token_type: OAuth2Tokentype) -> DalResult<Option<TokenRecord>> {
+        let row = match self.conn().await?.query_opt("SELECT token, expiry FROM oauth2_tokens WHERE connection_id = $1 AND token_type = $2", &[&connection_id, &token_type.get_token_type_string()]).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(TokenRecord {
+            token: row.get("token"),
+            expiry: row.get("expiry"),
+        }))
+    }
+
+    async fn list_tokens(&self) -> DalResult<Vec<StoredTokenRecord>> {
+        let rows = self.conn().await?.query("SELECT connection_id, token_type, token FROM oauth2_tokens", &[]).await?;
+
+        rows.iter()
+            .map(|row| Ok(StoredTokenRecord {
+                connection_id: row.get("connection_id"),
+                token_type: parse_token_type(row.get("token_type"))?,
+                token: row.get("token"),
+            }))
+            .collect()
+    }
+
+    async fn replace_token(&self, connection_id: &str, token_type: OAuth2Tokentype, current: &str, new: &str) -> DalResult<bool> {
+        let replaced = self.conn().await?.execute("UPDATE oauth2_tokens SET token = $1 WHERE connection_id = $2 AND token_type = $3 AND token = $4", &[
+            &new,
+            &connection_id,
+            &token_type.get_token_type_string(),
+            &current,
+        ]).await?;
+
+        Ok(replaced > 0)
+    }
+
+    async fn set_exact_user(&self, connection_id: &str, exact_user: &ExactUser) -> DalResult<()> {
+        self.conn().await?.execute("INSERT INTO exact_users (connection_id, exact_user_id, full_name, email, current_division) VALUES ($1, $2, $3, $4, $5) \
+            ON CONFLICT (connection_id) DO UPDATE SET exact_user_id = EXCLUDED.exact_user_id, full_name = EXCLUDED.full_name, email = EXCLUDED.email, current_division = EXCLUDED.current_division", &[
+            &connection_id,
+            &exact_user.exact_user_id,
+            &exact_user.full_name,
+            &exact_user.email,
+            &exact_user.current_division,
+        ]).await?;
+
+        Ok(())
+    }
+
+    async fn get_exact_user(&self, connection_id: &str) -> DalResult<Option<ExactUser>> {
+        let row = match self.conn().await?.query_opt("SELECT exact_user_id, full_name, email, current_division FROM exact_users WHERE connection_id = $1", &[&connection_id]).await? {
+            Some(x) => x,
+            None => return Ok(None)
+        };
+
+        Ok(Some(ExactUser {
+            exact_user_id: row.get("exact_user_id"),
+            full_name: row.get("full_name"),
+            email: row.get("email"),
+            current_division: row.get("current_division"),
+        }))
+    }
+
+    async fn try_acquire_refresh_lease(&self, connection_id: &str, holder: &str, now: i64, expires_at: i64) -> DalResult<bool> {
+        // The conflicting row is only updated, and thus counted, if the lease may be acquired
+        let acquired = self.conn().await?.execute("INSERT INTO refresh_leases (connection_id, holder, expires_at) VALUES ($1, $2, $3) \
+            ON CONFLICT (connection_id) DO UPDATE SET holder = EXCLUDED.holder, expires_at = EXCLUDED.expires_at \
+            WHERE refresh_leases.expires_at < $4 OR refresh_leases.holder = EXCLUDED.holder", &[
+            &connection_id,
+            &holder,
+            &expires_at,
+            &now,
+        ]).await?;
+
+        Ok(acquired > 0)
+    }
+
+    async fn release_refresh_lease(&self, connection_id: &str, holder: &str) -> DalResult<()> {
+        self.conn().await?.execute("DELETE FROM refresh_leases WHERE connection_id = $1 AND holder = $2", &[&connection_id, &holder]).await?;
+        Ok(())
+    }
+
+    async fn create_audit_event(&self, timestamp: i64, event: &NewAuditEvent) -> DalResult<()> {
+        self.conn().await?.execute("INSERT INTO audit_events (timestamp, actor, user_id, connection_id, grantee_id, action, outcome, detail, ip, user_agent) \
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)", &[
+            &timestamp,
+            &event.actor,
+            &event.user_id,
+            &event.connection_id,
+            &event.grantee_id,
+            &event.action.as_str(),
+            &event.outcome.as_str(),
+            &event.detail,
+            &event.ip,
+            &event.user_agent,
+        ]).await?;
+
+        Ok(())
+    }
+
+    async fn list_audit_events(&self, filter: &AuditEventFilter, limit: u32) -> DalResult<Vec<AuditEvent>> {
+        // The parameters are cast, as their type can not be inferred from `IS NULL`
+        let rows = self.conn().await?.query("SELECT id, timestamp, actor, user_id, connection_id, grantee_id, action, outcome, detail, ip, user_agent FROM audit_events \
+            WHERE ($1::VARCHAR IS NULL OR actor = $1) \
+            AND ($2::VARCHAR IS NULL OR user_id = $2) \
+            AND ($3::VARCHAR IS NULL OR action = $3) \
+            AND ($4::VARCHAR IS NULL OR outcome = $4) \
+            AND ($5::BIGINT IS NULL OR timestamp >= $5) \
+            AND ($6::BIGINT IS NULL OR timestamp <= $6) \
+            AND ($7::BIGINT IS NULL OR id < $7) \
+            ORDER BY id DESC LIMIT $8", &[
+            &filter.actor,
+            &filter.user_id,
+            &filter.action.map(|action| action.as_str()),
+            &filter.outcome.map(|outcome| outcome.as_str()),
+            &filter.from,
+            &filter.to,
+            &filter.before_id,
+            &(limit as i64),
+        ]).await?;
+
+        rows.iter()
+            .map(|row| Ok(AuditEvent {
+                id: row.get("id"),
+                timestamp: row.get("timestamp"),
+                actor: row.get("actor"),
+                user_id: row.get("user_id"),
+                connection_id: row.get("connection_id"),
+                grantee_id: row.get("grantee_id"),
+                action: parse_audit_action(row.get("action"))?,
+                outcome: parse_audit_outcome(row.get("outcome"))?,
+                detail: row.get("detail"),
+                ip: row.get("ip"),
+                user_agent: row.get("user_agent"),
+            }))
+            .collect()
+    }
+}
+
+/// Embedded migrations
+mod migrations {
+    use refinery::embed_migrations;
+    embed_migrations!("./migrations/postgres");
+}
@@ -0,0 +1,857 @@
+use async_trait::async_trait;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OptionalExtension, params, Row};
+use crate::{AuditEvent, AuditEventFilter, DalResult, ExactUser, NewAuditEvent, OAuth2Tokentype, Region};
+use crate::storage::{AuthorizationStartRecord, ConnectionGrantRecord, ConnectionRecord, LoginTicketRecord, OwnerRecord, parse_audit_action, parse_audit_outcome, parse_owner_kind, parse_region, parse_token_type, Storage, StoredTokenRecord, TokenRecord, UserRecord};
+
+/// The path which opens a database that only lives in memory
+const IN_MEMORY_PATH: &str = ":memory:";
+
+/// SQLite storage backend.
+///
+/// SQLite has no asynchronous driver, every query is run on tokio's blocking thread pool
+pub struct SqliteStorage(Pool<SqliteConnectionManager>);
+
+impl SqliteStorage {
+    /// Open the database at `path`, creating it if it does not exist, and apply migrations.
+    /// If `path` is `:memory:` the database only lives in memory, for as long as `Self` does.
+    ///
+    /// # Errors
+    ///
+    /// - If the database could not be opened
+    /// - If applying the migrations fails
+    pub async fn new(path: &str) -> DalResult<Self> {
+        let manager = if path == IN_MEMORY_PATH {
+            SqliteConnectionManager::memory()
+        } else {
+            SqliteConnectionManager::file(path)
+        }.with_init(|conn| conn.execute_batch("PRAGMA foreign_keys = ON;"));
+
+        // Every connection to an in-memory database opens a new database
+        let max_size = if path == IN_MEMORY_PATH { 1 } else { 10 };
+
+        let pool = tokio::task::spawn_blocking(move || -> DalResult<_> {
+            let pool = Pool::builder()
+                .max_size(max_size)
+                .build(manager)?;
+
+            let mut conn = pool.get()?;
+            migrations::migrations::runner()
+                .set_migration_table_name("__mrauth_migrations")
+                .run(&mut *conn)?;
+
+            Ok(pool)
+        }).await??;
+
+        Ok(Self(pool))
+    }
+
+    /// Run `f` with a connection from the pool, on the blocking thread pool
+    async fn with_conn<T, F>(&self, f: F) -> DalResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> DalResult<T> + Send + 'static,
+    {
+        let pool = self.0.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = pool.get()?;
+            f(&conn)
+        }).await?
+    }
+}
+
+/// Deletes a connection and everything referencing it, in order, with the logins its user did not complete.
+/// Every statement takes the connection ID
+const DELETE_CONNECTION_STATEMENTS: [&str; 7] = [
+    "DELETE FROM oauth2_authorization_start WHERE user_id = (SELECT user_id FROM connections WHERE id = ?1)",
+    "DELETE FROM login_tickets WHERE user_id = (SELECT user_id FROM connections WHERE id = ?1)",
+    "DELETE FROM oauth2_tokens WHERE connection_id = ?1",
+    "DELETE FROM exact_users WHERE connection_id = ?1",
+    "DELETE FROM refresh_leases WHERE connection_id = ?1",
+    "DELETE FROM connection_grants WHERE connection_id = ?1",
+    "DELETE FROM connections WHERE id = ?1",
+];
+
+/// Deletes everything referencing a user, in order, before the user itself is deleted. Every statement takes the user ID.
+/// Shared connections are left alone, the user can not be deleted while it logged in to any
+const DELETE_USER_STATEMENTS: [&str; 7] = [
+    "DELETE FROM oauth2_tokens WHERE connection_id IN (SELECT id FROM connections WHERE user_id = ?1 AND owner_id IS NULL)",
+    "DELETE FROM exact_users WHERE connection_id IN (SELECT id FROM connections WHERE user_id = ?1 AND owner_id IS NULL)",
+    "DELETE FROM refresh_leases WHERE connection_id IN (SELECT id FROM connections WHERE user_id = ?1 AND owner_id IS NULL)",
+    "DELETE FROM connection_grants WHERE connection_id IN (SELECT id FROM connections WHERE user_id = ?1 AND owner_id IS NULL) OR grantee_id = ?1",
+    "DELETE FROM connections WHERE user_id = ?1 AND owner_id IS NULL",
+    "DELETE FROM oauth2_authorization_start WHERE user_id = ?1",
+    "DELETE FROM login_tickets WHERE user_id = ?1",
+];
+
+const CONNECTION_COLUMNS: &str = "connections.id, connections.user_id, connections.owner_id, connections.label, connections.region, \
+    connections.reauthorization_required, connections.refresh_failures, connections.refresh_retry_at, \
+    connections.scopes, connections.last_refreshed_at";
+
+/// A connection as read from a row, the region is parsed after the row is read
+struct RawConnection {
+    id: String,
+    user_id: String,
+    owner_id: Option<String>,
+    label: Option<String>,
+    region: String,
+    reauthorization_required: bool,
+    refresh_failures: u32,
+    refresh_retry_at: Option<i64>,
+    scopes: Option<String>,
+    last_refreshed_at: Option<i64>,
+}
+
+impl RawConnection {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            user_id: row.get("user_id")?,
+            owner_id: row.get("owner_id")?,
+            label: row.get("label")?,
+            region: row.get("region")?,
+            reauthorization_required: row.get("reauthorization_required")?,
+            refresh_failures: row.get("refresh_failures")?,
+            refresh_retry_at: row.get("refresh_retry_at")?,
+            scopes: row.get("scopes")?,
+            last_refreshed_at: row.get("last_refreshed_at")?,
+        })
+    }
+
+    fn into_record(self) -> DalResult<ConnectionRecord> {
+        Ok(ConnectionRecord {
+            id: self.id,
+            user_id: self.user_id,
+            owner_id: self.owner_id,
+            label: self.label,
+            region: parse_region(&self.region)?,
+            reauthorization_required: self.reauthorization_required,
+            refresh_failures: self.refresh_failures,
+            refresh_retry_at: self.refresh_retry_at,
+            scopes: self.scopes,
+            last_refreshed_at: self.last_refreshed_at,
+        })
+    }
+}
+
+/// An owner as read from a row, the kind is parsed after the row is read
+struct RawOwner {
+    id: String,
+    name: String,
+    kind: String,
+    created_at: i64,
+}
+
+impl RawOwner {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            name: row.get("name")?,
+            kind: row.get("kind")?,
+            created_at: row.get("created_at")?,
+        })
+    }
+
+    fn into_record(self) -> DalResult<OwnerRecord> {
+        Ok(OwnerRecord {
+            id: self.id,
+            name: self.name,
+            kind: parse_owner_kind(&self.kind)?,
+            created_at: self.created_at,
+        })
+    }
+}
+
+fn connection_grant_from_row(row: &Row) -> rusqlite::Result<ConnectionGrantRecord> {
+    Ok(ConnectionGrantRecord {
+        connection_id: row.get("connection_id")?,
+        grantee_id: row.get("grantee_id")?,
+        granted_by: row.get("granted_by")?,
+        granted_at: row.get("granted_at")?,
+    })
+}
+
+/// An audit event as read from a row, the action and outcome are parsed after the row is read
+struct RawAuditEvent {
+    id: i64,
+    timestamp: i64,
+    actor: String,
+    user_id: Option<String>,
+    connection_id: Option<String>,
+    grantee_id: Option<String>,
+    action: String,
+    outcome: String,
+    detail: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl RawAuditEvent {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            timestamp: row.get("timestamp")?,
+            actor: row.get("actor")?,
+            user_id: row.get("user_id")?,
+            connection_id: row.get("connection_id")?,
+            grantee_id: row.get("grantee_id")?,
+            action: row.get("action")?,
+            outcome: row.get("outcome")?,
+            detail: row.get("detail")?,
+            ip: row.get("ip")?,
+            user_agent: row.get("user_agent")?,
+        })
+    }
+
+    fn into_event(self) -> DalResult<AuditEvent> {
+        Ok(AuditEvent {
+            id: self.id,
+            timestamp: self.timestamp,
+            actor: self.actor,
+            user_id: self.user_id,
+            connection_id: self.connection_id,
+            grantee_id: self.grantee_id,
+            action: parse_audit_action(&self.action)?,
+            outcome: parse_audit_outcome(&self.outcome)?,
+            detail: self.detail,
+            ip: self.ip,
+            user_agent: self.user_agent,
+        })
+    }
+}
+
+/// The columns shared by authorization starts and login tickets, as read from a row
+struct RawStart {
+    user_id: String,
+    timestamp: i64,
+    caller: String,
+    exact_scopes: String,
+    region: String,
+    connection_label: Option<String>,
+    connection_id: Option<String>,
+}
+
+impl RawStart {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            user_id: row.get("user_id")?,
+            timestamp: row.get("timestamp")?,
+            caller: row.get("caller")?,
+            exact_scopes: row.get("scopes")?,
+            region: row.get("region")?,
+            connection_label: row.get("connection_label")?,
+            connection_id: row.get("connection_id")?,
+        })
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn list_users(&self) -> DalResult<Vec<UserRecord>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT id FROM users")?;
+            let users = stmt.query_map([], |row| Ok(UserRecord { id: row.get("id")? }))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(users)
+        }).await
+    }
+
+    async fn get_user(&self, id: &str) -> DalResult<Option<UserRecord>> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let user = conn.query_row("SELECT id FROM users WHERE id = ?1", params![id], |row| Ok(UserRecord { id: row.get("id")? })).optional()?;
+            Ok(user)
+        }).await
+    }
+
+    async fn create_user(&self, id: &str) -> DalResult<()> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            conn.execute("INSERT INTO users (id) VALUES (?1)", params![id])?;
+            Ok(())
+        }).await
+    }
+
+    async fn create_connection(&self, connection: &ConnectionRecord) -> DalResult<ConnectionRecord> {
+        let connection = connection.clone();
+        self.with_conn(move |conn| {
+            conn.execute("INSERT INTO connections (id, user_id, owner_id, label, region, reauthorization_required, refresh_failures, refresh_retry_at, scopes, last_refreshed_at) \
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10) \
+                ON CONFLICT (user_id, COALESCE(label, '')) WHERE owner_id IS NULL DO UPDATE SET region = excluded.region, scopes = excluded.scopes", params![
+                connection.id,
+                connection.user_id,
+                connection.owner_id,
+                connection.label,
+                connection.region.as_str(),
+                connection.reauthorization_required,
+                connection.refresh_failures,
+                connection.refresh_retry_at,
+                connection.scopes,
+                connection.last_refreshed_at,
+            ])?;
+
+            conn.query_row(&format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE user_id = ?1 AND label IS ?2 AND owner_id IS NULL"), params![connection.user_id, connection.label], RawConnection::from_row)?
+                .into_record()
+        }).await
+    }
+
+    async fn get_connection(&self, id: &str) -> DalResult<Option<ConnectionRecord>> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let connection = match conn.query_row(&format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE id = ?1"), params![id], RawConnection::from_row).optional()? {
+                Some(x) => x,
+                None => return Ok(None)
+            };
+
+            Ok(Some(connection.into_record()?))
+        }).await
+    }
+
+    async fn list_connections(&self, user_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let user_id = user_id.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(&format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE user_id = ?1 AND owner_id IS NULL ORDER BY id"))?;
+            let connections = stmt.query_map(params![user_id], RawConnection::from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            connections.into_iter()
+                .map(RawConnection::into_record)
+                .collect()
+        }).await
+    }
+
+    async fn get_connection_by_label(&self, user_id: &str, label: Option<&str>) -> DalResult<Option<ConnectionRecord>> {
+        let user_id = user_id.to_string();
+        let label = label.map(str::to_string);
+        self.with_conn(move |conn| {
+            // `IS` also matches if both sides are NULL
+            let connection = match conn.query_row(&format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE user_id = ?1 AND label IS ?2 AND owner_id IS NULL"), params![user_id, label], RawConnection::from_row).optional()? {
+                Some(x) => x,
+                None => return Ok(None)
+            };
+
+            Ok(Some(connection.into_record()?))
+        }).await
+    }
+
+    async fn set_connection_region(&self, id: &str, region: Region) -> DalResult<()> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            conn.execute("UPDATE connections SET region = ?1 WHERE id = ?2", params![region.as_str(), id])?;
+            Ok(())
+        }).await
+    }
+
+    async fn set_connection_scopes(&self, id: &str, scopes: &str) -> DalResult<()> {
+        let id = id.to_string();
+        let scopes = scopes.to_string();
+        self.with_conn(move |conn| {
+            conn.execute("UPDATE connections SET scopes = ?1 WHERE id = ?2", params![scopes, id])?;
+            Ok(())
+        }).await
+    }
+
+    async fn set_connection_last_refreshed_at(&self, id: &str, last_refreshed_at: i64) -> DalResult<()> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            conn.execute("UPDATE connections SET last_refreshed_at = ?1 WHERE id = ?2", params![last_refreshed_at, id])?;
+            Ok(())
+        }).await
+    }
+
+    async fn set_connection_owner(&self, id: &str, owner_id: Option<&str>) -> DalResult<()> {
+        let id = id.to_string();
+        let owner_id = owner_id.map(str::to_string);
+        self.with_conn(move |conn| {
+            conn.execute("UPDATE connections SET owner_id = ?1 WHERE id = ?2", params![owner_id, id])?;
+            Ok(())
+        }).await
+    }
+
+    async fn create_owner(&self, owner: &OwnerRecord) -> DalResult<()> {
+        let owner = owner.clone();
+        self.with_conn(move |conn| {
+            conn.execute("INSERT INTO connection_owners (id, name, kind, created_at) VALUES (?1, ?2, ?3, ?4)", params![
+                owner.id,
+                owner.name,
+                owner.kind.as_str(),
+                owner.created_at,
+            ])?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn get_owner(&self, id: &str) -> DalResult<Option<OwnerRecord>> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let owner = match conn.query_row("SELECT id, name, kind, created_at FROM connection_owners WHERE id = ?1", params![id], RawOwner::from_row).optional()? {
+                Some(x) => x,
+                None => return Ok(None)
+            };
+
+            Ok(Some(owner.into_record()?))
+        }).await
+    }
+
+    async fn list_owners(&self) -> DalResult<Vec<OwnerRecord>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT id, name, kind, created_at FROM connection_owners ORDER BY name")?;
+            let owners = stmt.query_map([], RawOwner::from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            owners.into_iter()
+                .map(RawOwner::into_record)
+                .collect()
+        }).await
+    }
+
+    async fn list_owner_connections(&self, owner_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let owner_id = owner_id.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(&format!("SELECT {CONNECTION_COLUMNS} FROM connections WHERE owner_id = ?1 ORDER BY id"))?;
+            let connections = stmt.query_map(params![owner_id], RawConnection::from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            connections.into_iter()
+                .map(RawConnection::into_record)
+                .collect()
+        }).await
+    }
+
+    async fn create_connection_grant(&self, grant: &ConnectionGrantRecord) -> DalResult<bool> {
+        let grant = grant.clone();
+        self.with_conn(move |conn| {
+            let inserted = conn.execute("INSERT OR IGNORE INTO connection_grants (connection_id, grantee_id, granted_by, granted_at) \
+                VALUES (?1, ?2, ?3, ?4)", params![
+                grant.connection_id,
+                grant.grantee_id,
+                grant.granted_by,
+                grant.granted_at,
+            ])?;
+
+            Ok(inserted > 0)
+        }).await
+    }
+
+    async fn delete_connection_grant(&self, connection_id: &str, grantee_id: &str) -> DalResult<bool> {
+        let connection_id = connection_id.to_string();
+        let grantee_id = grantee_id.to_string();
+        self.with_conn(move |conn| {
+            let deleted = conn.execute("DELETE FROM connection_grants WHERE connection_id = ?1 AND grantee_id = ?2", params![connection_id, grantee_id])?;
+            Ok(deleted > 0)
+        }).await
+    }
+
+    async fn list_connection_grants(&self, connection_id: &str) -> DalResult<Vec<ConnectionGrantRecord>> {
+        let connection_id = connection_id.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare("SELECT connection_id, grantee_id, granted_by, granted_at FROM connection_grants \
+                WHERE connection_id = ?1 ORDER BY grantee_id")?;
+            let grants = stmt.query_map(params![connection_id], connection_grant_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            Ok(grants)
+        }).await
+    }
+
+    async fn has_connection_grant(&self, connection_id: &str, grantee_id: &str) -> DalResult<bool> {
+        let connection_id = connection_id.to_string();
+        let grantee_id = grantee_id.to_string();
+        self.with_conn(move |conn| {
+            let found: Option<String> = conn.query_row("SELECT grantee_id FROM connection_grants WHERE connection_id = ?1 AND grantee_id = ?2", params![connection_id, grantee_id], |row| row.get(0)).optional()?;
+            Ok(found.is_some())
+        }).await
+    }
+
+    async fn list_granted_connections(&self, grantee_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let grantee_id = grantee_id.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(&format!("SELECT {CONNECTION_COLUMNS} \
+                FROM connection_grants INNER JOIN connections ON connections.id = connection_grants.connection_id \
+                WHERE connection_grants.grantee_id = ?1 AND connections.owner_id IS NOT NULL \
+                ORDER BY connections.id"))?;
+            let connections = stmt.query_map(params![grantee_id], RawConnection::from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            connections.into_iter()
+                .map(RawConnection::into_record)
+                .collect()
+        }).await
+    }
+
+    async fn list_shared_connections_of_user(&self, user_id: &str) -> DalResult<Vec<ConnectionRecord>> {
+        let user_id = user_id.to_string();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(&format!("SELECT {CONNECTION_COLUMNS} FROM connections \
+                WHERE user_id = ?1 AND owner_id IS NOT NULL ORDER BY id"))?;
+            let connections = stmt.query_map(params![user_id], RawConnection::from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            connections.into_iter()
+                .map(RawConnection::into_record)
+                .collect()
+        }).await
+    }
+
+    async fn set_reauthorization_required(&self, connection_id: &str) -> DalResult<()> {
+        let connection_id = connection_id.to_string();
+        self.with_conn(move |conn| {
+            conn.execute("UPDATE connections SET reauthorization_required = TRUE, refresh_retry_at = NULL WHERE id = ?1", params![connection_id])?;
+            Ok(())
+        }).await
+    }
+
+    async fn record_refresh_failure(&self, connection_id: &str, retry_at: i64) -> DalResult<()> {
+        let connection_id = connection_id.to_string();
+        self.with_conn(move |conn| {
+            conn.execute("UPDATE connections SET refresh_failures = refresh_failures + 1, refresh_retry_at = ?1 WHERE id = ?2", params![retry_at, connection_id])?;
+            Ok(())
+        }).await
+    }
+
+    async fn reset_refresh_state(&self, connection_id: &str) -> DalResult<()> {
+        let connection_id = connection_id.to_string();
+        self.with_conn(move |conn| {
+            conn.execute("UPDATE connections SET reauthorization_required = FALSE, refresh_failures = 0, refresh_retry_at = NULL WHERE id = ?1", params![connection_id])?;
+            Ok(())
+        }).await
+    }
+
+    async fn list_refresh_due(&self, expires_before: i64, now: i64) -> DalResult<Vec<ConnectionRecord>> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare(&format!("SELECT {CONNECTION_COLUMNS} \
+                FROM oauth2_tokens INNER JOIN connections ON connections.id = oauth2_tokens.connection_id \
+                WHERE oauth2_tokens.token_type = ?1 AND oauth2_tokens.expiry <= ?2 \
+                AND connections.reauthorization_required = FALSE AND (connections.refresh_retry_at IS NULL OR connections.refresh_retry_at <= ?3) \
+                ORDER BY oauth2_tokens.expiry"))?;
+            let connections = stmt.query_map(params![OAuth2Tokentype::Access.get_token_type_string(), expires_before, now], RawConnection::from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            connections.into_iter()
+                .map(RawConnection::into_record)
+                .collect()
+        }).await
+    }
+
+    async fn next_refresh_due(&self, window_sec: i64) -> DalResult<Option<i64>> {
+        self.with_conn(move |conn| {
+            // SQLite's multi-argument MAX is the scalar equivalent of GREATEST
+            let next_due = conn.query_row("SELECT MIN(MAX(oauth2_tokens.expiry - ?1, COALESCE(connections.refresh_retry_at, 0))) \
+                FROM oauth2_tokens INNER JOIN connections ON connections.id = oauth2_tokens.connection_id \
+                WHERE oauth2_tokens.token_type = ?2 AND connections.reauthorization_required = FALSE", params![window_sec, OAuth2Tokentype::Access.get_token_type_string()], |row| row.get(0))?;
+
+            Ok(next_due)
+        }).await
+    }
+
+    async fn delete_connection(&self, id: &str) -> DalResult<()> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            // Only a shared reference to the connection is available, nothing else uses it while the transaction is open
+            let tx = conn.unchecked_transaction()?;
+            for statement in DELETE_CONNECTION_STATEMENTS {
+                tx.execute(statement, params![id])?;
+            }
+            tx.commit()?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn delete_user(&self, id: &str, pseudonym: &str) -> DalResult<bool> {
+        let id = id.to_string();
+        let pseudonym = pseudonym.to_string();
+        self.with_conn(move |conn| {
+            // Only a shared reference to the connection is available, nothing else uses it while the transaction is open
+            let tx = conn.unchecked_transaction()?;
+            for statement in DELETE_USER_STATEMENTS {
+                tx.execute(statement, params![id])?;
+            }
+
+            tx.execute("UPDATE audit_events SET ip = NULL, user_agent = NULL WHERE user_id = ?1 OR actor = ?1", params![id])?;
+            tx.execute("UPDATE audit_events SET actor = ?1 WHERE actor = ?2", params![pseudonym, id])?;
+            tx.execute("UPDATE audit_events SET user_id = ?1 WHERE user_id = ?2", params![pseudonym, id])?;
+            tx.execute("UPDATE audit_events SET grantee_id = ?1 WHERE grantee_id = ?2", params![pseudonym, id])?;
+
+            let deleted = tx.execute("DELETE FROM users WHERE id = ?1", params![id])?;
+            tx.commit()?;
+
+            Ok(deleted > 0)
+        }).await
+    }
+
+    async fn create_authorization_start(&self, start: &AuthorizationStartRecord) -> DalResult<()> {
+        let start = start.clone();
+        self.with_conn(move |conn| {
+            conn.execute("INSERT INTO oauth2_authorization_start (id, user_id, timestamp, caller, scopes, region, connection_label, connection_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)", params![
+                start.id,
+                start.user_id,
+                start.timestamp,
+                start.caller,
+                start.exact_scopes,
+                start.region.as_str(),
+                start.connection_label,
+                start.connection_id,
+            ])?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn consume_authorization_start(&self, id: &str) -> DalResult<Option<AuthorizationStartRecord>> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let start = match conn.query_row("DELETE FROM oauth2_authorization_start WHERE id = ?1 RETURNING user_id, timestamp, caller, scopes, region, connection_label, connection_id", params![id], RawStart::from_row).optional()? {
+                Some(x) => x,
+                None => return Ok(None)
+            };
+
+            Ok(Some(AuthorizationStartRecord {
+                id,
+                user_id: start.user_id,
+                timestamp: start.timestamp,
+                caller: start.caller,
+                exact_scopes: start.exact_scopes,
+                region: parse_region(&start.region)?,
+                connection_label: start.connection_label,
+                connection_id: start.connection_id,
+            }))
+        }).await
+    }
+
+    async fn delete_authorization_starts_before(&self, timestamp: i64) -> DalResult<u64> {
+        self.with_conn(move |conn| {
+            let deleted = conn.execute("DELETE FROM oauth2_authorization_start WHERE timestamp < ?1", params![timestamp])?;
+            Ok(deleted as u64)
+        }).await
+    }
+
+    async fn create_login_ticket(&self, ticket: &LoginTicketRecord) -> DalResult<()> {
+        let ticket = ticket.clone();
+        self.with_conn(move |conn| {
+            conn.execute("INSERT INTO login_tickets (id, user_id, timestamp, caller, scopes, region, connection_label, connection_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)", params![
+                ticket.id,
+                ticket.user_id,
+                ticket.timestamp,
+                ticket.caller,
+                ticket.exact_scopes,
+                ticket.region.as_str(),
+                ticket.connection_label,
+                ticket.connection_id,
+            ])?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn consume_login_ticket(&self, id: &str) -> DalResult<Option<LoginTicketRecord>> {
+        let id = id.to_string();
+        self.with_conn(move |conn| {
+            let ticket = match conn.query_row("DELETE FROM login_tickets WHERE id = ?1 RETURNING user_id, timestamp, caller, scopes, region, connection_label, connection_id", params![id], RawStart::from_row).optional()? {
+                Some(x) => x,
+                None => return Ok(None)
+            };
+
+            Ok(Some(LoginTicketRecord {
+                id,
+                user_id: ticket.user_id,
+                timestamp: ticket.timestamp,
+                caller: ticket.caller,
+                exact_scopes: ticket.exact_scopes,
+                region: parse_region(&ticket.region)?,
+                connection_label: ticket.connection_label,
+                connection_id: ticket.connection_id,
+            }))
+        }).await
+    }
+
+    async fn delete_login_tickets_before(&self, timestamp: i64) -> DalResult<u64> {
+        self.with_conn(move |conn| {
+            let deleted = conn.execute("DELETE FROM login_tickets WHERE timestamp < ?1", params![timestamp])?;
+            Ok(deleted as u64)
+        }).await
+    }
+
+    async fn set_token_pair(&self, connection_id: &str, access: &TokenRecord, refresh: &TokenRecord) -> DalResult<()> {
+        let connection_id = connection_id.to_string();
+        let tokens = [(OAuth2Tokentype::Access, access.clone()), (OAuth2Tokentype::Refresh, refresh.clone())];
+        self.with_conn(move |conn| {
+            // Only a shared reference to the connection is available, nothing else uses it while the transaction is open
+            let tx = conn.unchecked_transaction()?;
+            for (token_type, record) in tokens {
+                tx.execute("INSERT INTO oauth2_tokens (connection_id, token, token_type, expiry) VALUES (?1, ?2, ?3, ?4) \
+                    ON CONFLICT (connection_id, token_type) DO UPDATE SET token = excluded.token, expiry = excluded.expiry", params![
+                    connection_id,
+                    record.token,
+                    token_type.get_token_type_string(),
+                    record.expiry,
+                ])?;
+            }
+            tx.commit()?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn get_token(&self, connection_id: &str, token_type: OAuth2Tokentype) -> DalResult<Option<TokenRecord>> {
+        let connection_id = connection_id.to_string();
+        self.with_conn(move |conn| {
+            let token = conn.query_row("SELECT token, expiry FROM oauth2_tokens WHERE connection_id = ?1 AND token_type = ?2", params![connection_id, token_type.get_token_type_string()], |row| Ok(TokenRecord {
+                token: row.get("token")?,
+                expiry: row.get("expiry")?,
+            })).optional()?;
+
+            Ok(token)
+        }).await
+    }
+
+    async fn list_tokens(&self) -> DalResult<Vec<StoredTokenRecord>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT connection_id, token_type, token FROM oauth2_tokens")?;
+            let tokens = stmt.query_map([], |row| Ok((row.get::<_, String>("connection_id")?, row.get::<_, String>("token_type")?, row.get::<_, String>("token")?)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            tokens.into_iter()
+                .map(|(connection_id, token_type, token)| Ok(StoredTokenRecord {
+                    connection_id,
+                    token_type: parse_token_type(&token_type)?,
+                    token,
+                }))
+                .collect()
+        }).await
+    }
+
+    async fn replace_token(&self, connection_id: &str, token_type: OAuth2Tokentype, current: &str, new: &str) -> DalResult<bool> {
+        let connection_id = connection_id.to_string();
+        let current = current.to_string();
+        let new = new.to_string();
+        self.with_conn(move |conn| {
+            let replaced = conn.execute("UPDATE oauth2_tokens SET token = ?1 WHERE connection_id = ?2 AND token_type = ?3 AND token = ?4", params![
+                new,
+                connection_id,
+                token_type.get_token_type_string(),
+                current,
+            ])?;
+
+            Ok(replaced > 0)
+        }).await
+    }
+
+    async fn set_exact_user(&self, connection_id: &str, exact_user: &ExactUser) -> DalResult<()> {
+        let connection_id = connection_id.to_string();
+        let exact_user = exact_user.clone();
+        self.with_conn(move |conn| {
+            conn.execute("INSERT INTO exact_users (connection_id, exact_user_id, full_name, email, current_division) VALUES (?1, ?2, ?3, ?4, ?5) \
+                ON CONFLICT (connection_id) DO UPDATE SET exact_user_id = excluded.exact_user_id, full_name = excluded.full_name, email = excluded.email, current_division = excluded.current_division", params![
+                connection_id,
+                exact_user.exact_user_id,
+                exact_user.full_name,
+                exact_user.email,
+                exact_user.current_division,
+            ])?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn get_exact_user(&self, connection_id: &str) -> DalResult<Option<ExactUser>> {
+        let connection_id = connection_id.to_string();
+        self.with_conn(move |conn| {
+            let exact_user = conn.query_row("SELECT exact_user_id, full_name, email, current_division FROM exact_users WHERE connection_id = ?1", params![connection_id], |row| Ok(ExactUser {
+                exact_user_id: row.get("exact_user_id")?,
+                full_name: row.get("full_name")?,
+                email: row.get("email")?,
+                current_division: row.get("current_division")?,
+            })).optional()?;
+
+            Ok(exact_user)
+        }).await
+    }
+
+    async fn try_acquire_refresh_lease(&self, connection_id: &str, holder: &str, now: i64, expires_at: i64) -> DalResult<bool> {
+        let connection_id = connection_id.to_string();
+        let holder = holder.to_string();
+        self.with_conn(move |conn| {
+            // The conflicting row is only updated, and thus counted, if the lease may be acquired
+            let acquired = conn.execute("INSERT INTO refresh_leases (connection_id, holder, expires_at) VALUES (?1, ?2, ?3) \
+                ON CONFLICT (connection_id) DO UPDATE SET holder = excluded.holder, expires_at = excluded.expires_at \
+                WHERE refresh_leases.expires_at < ?4 OR refresh_leases.holder = excluded.holder", params![
+                connection_id,
+                holder,
+                expires_at,
+                now,
+            ])?;
+
+            Ok(acquired > 0)
+        }).await
+    }
+
+    async fn release_refresh_lease(&self, connection_id: &str, holder: &str) -> DalResult<()> {
+        let connection_id = connection_id.to_string();
+        let holder = holder.to_string();
+        self.with_conn(move |conn| {
+            conn.execute("DELETE FROM refresh_leases WHERE connection_id = ?1 AND holder = ?2", params![connection_id, holder])?;
+            Ok(())
+        }).await
+    }
+
+    async fn create_audit_event(&self, timestamp: i64, event: &NewAuditEvent) -> DalResult<()> {
+        let event = event.clone();
+        self.with_conn(move |conn| {
+            conn.execute("INSERT INTO audit_events (timestamp, actor, user_id, connection_id, grantee_id, action, outcome, detail, ip, user_agent) \
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)", params![
+                timestamp,
+                event.actor,
+                event.user_id,
+                event.connection_id,
+                event.grantee_id,
+                event.action.as_str(),
+                event.outcome.as_str(),
+                event.detail,
+                event.ip,
+                event.user_agent,
+            ])?;
+
+            Ok(())
+        }).await
+    }
+
+    async fn list_audit_events(&self, filter: &AuditEventFilter, limit: u32) -> DalResult<Vec<AuditEvent>> {
+        let filter = filter.clone();
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare("SELECT id, timestamp, actor, user_id, connection_id, grantee_id, action, outcome, detail, ip, user_agent FROM audit_events \
+                WHERE (?1 IS NULL OR actor = ?1) \
+                AND (?2 IS NULL OR user_id = ?2) \
+                AND (?3 IS NULL OR action = ?3) \
+                AND (?4 IS NULL OR outcome = ?4) \
+                AND (?5 IS NULL OR timestamp >= ?5) \
+                AND (?6 IS NULL OR timestamp <= ?6) \
+                AND (?7 IS NULL OR id < ?7) \
+                ORDER BY id DESC LIMIT ?8")?;
+            let events = stmt.query_map(params![
+                filter.actor,
+                filter.user_id,
+                filter.action.map(|action| action.as_str()),
+                filter.outcome.map(|outcome| outcome.as_str()),
+                filter.from,
+                filter.to,
+                filter.before_id,
+                limit,
+            ], RawAuditEvent::from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            events.into_iter()
+                .map(RawAuditEvent::into_event)
+                .collect()
+        }).await
+    }
+}
+
+/// Embedded migrations
+mod migrations {
+    use refinery::embed_migrations;
+    embed_migrations!("./migrations/sqlite");
+}
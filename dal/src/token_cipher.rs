@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use aes_gcm::{Aes256Gcm, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use crate::{DalResult, Error, OAuth2Tokentype};
+
+/// Prefix of the format without associated data, which can still be decrypted
+const FORMAT_PREFIX_V1: &str = "v1";
+/// Prefix of the current format, to allow changing the format in the future
+const FORMAT_PREFIX_V2: &str = "v2";
+const NONCE_LEN: usize = 12;
+
+/// Encrypts and decrypts OAuth2 tokens before they are written to, and after they are read from, the database.
+///
+/// Tokens are encrypted using AES-256-GCM and stored as `v2:<key id>:<base64(nonce || ciphertext)>`.
+/// The connection ID and token type are authenticated as associated data,
+/// so a token copied to another connection or swapped with the other token type fails to decrypt.
+/// Tokens in the older `v1` format were encrypted without associated data, and are decrypted without it.
+/// New tokens are always encrypted with the current key, while tokens encrypted with
+/// any of the other configured keys can still be decrypted. This allows rotating keys.
+/// Tokens stored before encryption was introduced are read as plaintext.
+pub struct TokenCipher {
+    current_key_id: String,
+    keys: HashMap<String, Aes256Gcm>,
+}
+
+impl TokenCipher {
+    /// Create a cipher from a list of keys formatted as `<key id>:<base64 key>,<key id>:<base64 key>`.
+    /// Every key must be 32 bytes long.
+    ///
+    /// # Errors
+    ///
+    /// - If the list is malformed
+    /// - If a key is not valid base64, or not 32 bytes long
+    /// - If `current_key_id` is not in the list
+    pub fn from_key_list(current_key_id: &str, key_list: &str) -> DalResult<Self> {
+        let keys = key_list.split(',')
+            .map(|entry| {
+                let (key_id, key) = entry.trim().split_once(':')
+                    .ok_or(Error::Encryption("Invalid key entry, expected '<key id>:<base64 key>'".into()))?;
+                let key = STANDARD.decode(key)
+                    .map_err(|e| Error::Encryption(format!("Key '{key_id}' is not valid base64: {e}")))?;
+                let cipher = Aes256Gcm::new_from_slice(&key)
+                    .map_err(|_| Error::Encryption(format!("Key '{key_id}' must be 32 bytes long")))?;
+
+                Ok((key_id.to_string(), cipher))
+            })
+            .collect::<DalResult<HashMap<_, _>>>()?;
+
+        if !keys.contains_key(current_key_id) {
+            return Err(Error::Encryption(format!("Current key '{current_key_id}' is not in the list of keys")));
+        }
+
+        Ok(Self {
+            current_key_id: current_key_id.to_string(),
+            keys,
+        })
+    }
+
+    /// Encrypt the token of the connection with the current key
+    pub(crate) fn encrypt(&self, token: &str, connection_id: &str, token_type: OAuth2Tokentype) -> DalResult<String> {
+        let cipher = &self.keys[&self.current_key_id];
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = associated_data(connection_id, token_type);
+        let ciphertext = cipher.encrypt(&nonce, Payload { msg: token.as_bytes(), aad: aad.as_bytes() })
+            .map_err(|_| Error::Encryption("Failed to encrypt token".into()))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+
+        Ok(format!("{FORMAT_PREFIX_V2}:{}:{}", self.current_key_id, STANDARD.encode(payload)))
+    }
+
+    /// Decrypt a stored token of the connection
+    pub(crate) fn decrypt(&self, stored: &str, connection_id: &str, token_type: OAuth2Tokentype) -> DalResult<String> {
+        let (format, key_id, payload) = match Self::split(stored) {
+            Some(x) => x,
+            None => return Ok(stored.to_string()),
+        };
+
+        let cipher = self.keys.get(key_id)
+            .ok_or(Error::Encryption(format!("Token is encrypted with unknown key '{key_id}'")))?;
+        let payload = STANDARD.decode(payload)
+            .map_err(|e| Error::Encryption(format!("Encrypted token is not valid base64: {e}")))?;
+        if payload.len() < NONCE_LEN {
+            return Err(Error::Encryption("Encrypted token is too short".into()));
+        }
+
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        let aad = match format {
+            FORMAT_PREFIX_V1 => String::new(),
+            _ => associated_data(connection_id, token_type),
+        };
+        let plaintext = cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: aad.as_bytes() })
+            .map_err(|_| Error::Encryption(format!("Failed to decrypt token with key '{key_id}'")))?;
+
+        String::from_utf8(plaintext)
+            .map_err(|_| Error::Encryption("Decrypted token is not valid UTF-8".into()))
+    }
+
+    /// Whether the stored token is encrypted with the current key in the current format.
+    /// If it is not, it should be re-encrypted.
+    pub(crate) fn is_current(&self, stored: &str) -> bool {
+        matches!(Self::split(stored), Some((FORMAT_PREFIX_V2, key_id, _)) if key_id == self.current_key_id)
+    }
+
+    /// Split a stored token into its format, key ID and payload.
+    /// Returns `None` if the token is not encrypted
+    fn split(stored: &str) -> Option<(&str, &str, &str)> {
+        let (prefix, rest) = stored.split_once(':')?;
+        if prefix != FORMAT_PREFIX_V1 && prefix != FORMAT_PREFIX_V2 {
+            return None;
+        }
+
+        let (key_id, payload) = rest.split_once(':')?;
+        Some((prefix, key_id, payload))
+    }
+}
+
+/// Binds a ciphertext to the connection and token type it was stored for
+fn associated_data(connection_id: &str, token_type: OAuth2Tokentype) -> String {
+    format!("{connection_id}:{}", token_type.get_token_type_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONNECTION_ID: &str = "connection";
+
+    fn key_list(keys: &[(&str, u8)]) -> String {
+        keys.iter()
+            .map(|(key_id, byte)| format!("{key_id}:{}", STANDARD.encode([*byte; 32])))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    #[test]
+    fn round_trip() {
+        let cipher = TokenCipher::from_key_list("a", &key_list(&[("a", 1)])).unwrap();
+
+        let stored = cipher.encrypt("token", CONNECTION_ID, OAuth2Tokentype::Access).unwrap();
+        assert!(stored.starts_with("v2:a:"));
+        assert_eq!(cipher.decrypt(&stored, CONNECTION_ID, OAuth2Tokentype::Access).unwrap(), "token");
+    }
+
+    #[test]
+    fn decrypts_with_non_current_key() {
+        let old = TokenCipher::from_key_list("a", &key_list(&[("a", 1)])).unwrap();
+        let stored = old.encrypt("token", CONNECTION_ID, OAuth2Tokentype::Refresh).unwrap();
+
+        let rotated = TokenCipher::from_key_list("b", &key_list(&[("a", 1), ("b", 2)])).unwrap();
+        assert_eq!(rotated.decrypt(&stored, CONNECTION_ID, OAuth2Tokentype::Refresh).unwrap(), "token");
+
+        let removed = TokenCipher::from_key_list("b", &key_list(&[("b", 2)])).unwrap();
+        assert!(removed.decrypt(&stored, CONNECTION_ID, OAuth2Tokentype::Refresh).is_err());
+    }
+
+    #[test]
+    fn is_current() {
+        let old = TokenCipher::from_key_list("a", &key_list(&[("a", 1)])).unwrap();
+        let rotated = TokenCipher::from_key_list("b", &key_list(&[("a", 1), ("b", 2)])).unwrap();
+
+        let stored = old.encrypt("token", CONNECTION_ID, OAuth2Tokentype::Access).unwrap();
+        assert!(old.is_current(&stored));
+        assert!(!rotated.is_current(&stored));
+        assert!(rotated.is_current(&rotated.encrypt("token", CONNECTION_ID, OAuth2Tokentype::Access).unwrap()));
+        assert!(!rotated.is_current("token"));
+    }
+
+    #[test]
+    fn plaintext_fallback() {
+        let cipher = TokenCipher::from_key_list("a", &key_list(&[("a", 1)])).unwrap();
+        assert_eq!(cipher.decrypt("token", CONNECTION_ID, OAuth2Tokentype::Access).unwrap(), "token");
+    }
+
+    #[test]
+    fn rejects_other_connection_or_token_type() {
+        let cipher = TokenCipher::from_key_list("a", &key_list(&[("a", 1)])).unwrap();
+        let stored = cipher.encrypt("token", CONNECTION_ID, OAuth2Tokentype::Access).unwrap();
+
+        assert!(cipher.decrypt(&stored, "other", OAuth2Tokentype::Access).is_err());
+        assert!(cipher.decrypt(&stored, CONNECTION_ID, OAuth2Tokentype::Refresh).is_err());
+    }
+
+    #[test]
+    fn decrypts_v1_without_associated_data() {
+        let cipher = TokenCipher::from_key_list("a", &key_list(&[("a", 1)])).unwrap();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher.keys["a"].encrypt(&nonce, b"token".as_ref()).unwrap();
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        let stored = format!("{FORMAT_PREFIX_V1}:a:{}", STANDARD.encode(payload));
+
+        assert_eq!(cipher.decrypt(&stored, CONNECTION_ID, OAuth2Tokentype::Access).unwrap(), "token");
+        assert!(!cipher.is_current(&stored));
+    }
+}
@@ -0,0 +1,17 @@
+use std::time::Duration;
+
+/// A scripted deviation from the normal handling of a token request.
+/// Every token request takes the next fault, if any, in the order they were pushed
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// Respond with `400 Bad Request` and an `invalid_grant` error, as if the grant was revoked
+    InvalidGrant,
+    /// Respond with the status and an HTML body, like Exact does when it is down for maintenance
+    ServerError(u16),
+    /// Respond with `429 Too Many Requests` and a `Retry-After` of the given number of seconds
+    TooManyRequests {
+        retry_after_sec: u64,
+    },
+    /// Wait before handling the request as usual
+    Delay(Duration),
+}
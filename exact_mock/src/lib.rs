@@ -0,0 +1,16 @@
+//! An in-process stand-in for Exact Online's OAuth2 endpoints, for tests.
+//!
+//! It reproduces the behaviour of Exact which ExactAuth depends on:
+//! - `expires_in` is returned as a string
+//! - Refresh tokens rotate, a refresh token can only be used once
+//! - Access tokens may only be refreshed within [REFRESH_WINDOW_SEC] of expiring
+//!
+//! Responses of the token endpoint can be scripted with [Fault]s.
+
+mod fault;
+pub use fault::*;
+
+mod server;
+pub use server::*;
+
+mod state;
@@ -0,0 +1,233 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use actix_web::{App, HttpResponse, HttpServer, web};
+use actix_web::cookie::time;
+use actix_web::dev::ServerHandle;
+use actix_web::http::{header, StatusCode};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use crate::Fault;
+use crate::state::{State, TokenPair};
+
+/// Path of the OAuth2 authorization endpoint, as on Exact
+pub const AUTH_PATH: &str = "/api/oauth2/auth";
+/// Path of the OAuth2 token endpoint, as on Exact
+pub const TOKEN_PATH: &str = "/api/oauth2/token";
+/// Access tokens may only be refreshed when they expire within this many seconds.
+/// Refreshing earlier is rejected with `invalid_request`, and leaves the refresh token valid
+pub const REFRESH_WINDOW_SEC: i64 = 30;
+
+/// A running mock of Exact's OAuth2 endpoints, listening on a random local port.
+/// The server is stopped when [MockExact::stop] is called, or when the runtime it was started on shuts down
+pub struct MockExact {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    handle: ServerHandle,
+}
+
+impl MockExact {
+    /// Start the server on the current Tokio runtime.
+    /// Only the client with the provided credentials is accepted
+    pub async fn start(client_id: &str, client_secret: &str) -> io::Result<Self> {
+        let state = Arc::new(Mutex::new(State::new(client_id, client_secret)));
+        let data = web::Data::from(state.clone());
+
+        let server = HttpServer::new(move || App::new()
+            .app_data(data.clone())
+            .route(AUTH_PATH, web::get().to(auth))
+            .route(TOKEN_PATH, web::post().to(token))
+        )
+            .workers(1)
+            .disable_signals()
+            .bind(("127.0.0.1", 0))?;
+
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        Ok(Self {
+            addr,
+            state,
+            handle,
+        })
+    }
+
+    /// The URL to use as Exact's base URL, for every region
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Issue an authorization code, as if a user completed the login and was redirected to `redirect_uri`
+    pub fn issue_code(&self, redirect_uri: &str) -> String {
+        self.state.lock().unwrap().issue_code(redirect_uri)
+    }
+
+    /// Set the number of seconds access tokens issued from now on are valid for. Defaults to 600.
+    /// A lifetime below [REFRESH_WINDOW_SEC] issues tokens which may be refreshed right away
+    pub fn set_access_token_lifetime(&self, lifetime_sec: i64) {
+        self.state.lock().unwrap().access_token_lifetime_sec = lifetime_sec;
+    }
+
+    /// Script the handling of a future token request
+    pub fn push_fault(&self, fault: Fault) {
+        self.state.lock().unwrap().faults.push_back(fault);
+    }
+
+    /// The number of requests the token endpoint received, including failed and retried requests
+    pub fn token_requests(&self) -> usize {
+        self.state.lock().unwrap().token_requests
+    }
+
+    /// Whether the refresh token was issued and not yet used or revoked
+    pub fn is_refresh_token_valid(&self, refresh_token: &str) -> bool {
+        self.state.lock().unwrap().refresh_tokens.contains_key(refresh_token)
+    }
+
+    /// Revoke the refresh token, as if the user disconnected the app in Exact
+    pub fn revoke(&self, refresh_token: &str) {
+        self.state.lock().unwrap().refresh_tokens.remove(refresh_token);
+    }
+
+    pub async fn stop(self) {
+        self.handle.stop(false).await;
+    }
+}
+
+#[derive(Deserialize)]
+struct AuthQuery {
+    client_id: String,
+    redirect_uri: String,
+    response_type: String,
+    state: Option<String>,
+}
+
+/// The user logs in and approves right away, and is redirected back with a code
+async fn auth(state: web::Data<Mutex<State>>, query: web::Query<AuthQuery>) -> HttpResponse {
+    let mut state = state.lock().unwrap();
+    if query.client_id != state.client_id || query.response_type != "code" {
+        return HttpResponse::BadRequest().body("Invalid request");
+    }
+
+    let mut redirect = match Url::parse(&query.redirect_uri) {
+        Ok(x) => x,
+        Err(_) => return HttpResponse::BadRequest().body("Invalid redirect_uri"),
+    };
+
+    let code = state.issue_code(&query.redirect_uri);
+    redirect.query_pairs_mut().append_pair("code", &code);
+    if let Some(oauth_state) = &query.state {
+        redirect.query_pairs_mut().append_pair("state", oauth_state);
+    }
+
+    HttpResponse::Found()
+        .insert_header((header::LOCATION, redirect.to_string()))
+        .finish()
+}
+
+#[derive(Deserialize)]
+struct TokenForm {
+    grant_type: String,
+    client_id: String,
+    client_secret: String,
+    redirect_uri: Option<String>,
+    code: Option<String>,
+    refresh_token: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TokenResponse {
+    access_token: String,
+    token_type: &'static str,
+    /// Exact returns the lifetime as a string
+    expires_in: String,
+    refresh_token: String,
+}
+
+impl From<TokenPair> for TokenResponse {
+    fn from(x: TokenPair) -> Self {
+        Self {
+            access_token: x.access,
+            token_type: "bearer",
+            expires_in: x.expires_in.to_string(),
+            refresh_token: x.refresh,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: &'static str,
+    error_description: &'static str,
+}
+
+async fn token(state: web::Data<Mutex<State>>, form: web::Form<TokenForm>) -> HttpResponse {
+    let fault = {
+        let mut state = state.lock().unwrap();
+        state.token_requests += 1;
+        state.faults.pop_front()
+    };
+
+    match fault {
+        Some(Fault::InvalidGrant) => return oauth2_error("invalid_grant", "The grant is invalid"),
+        Some(Fault::ServerError(status)) => return HttpResponse::build(StatusCode::from_u16(status).expect("Invalid status code"))
+            .content_type("text/html")
+            .body("<html><body><h1>Exact Online is temporarily unavailable</h1></body></html>"),
+        Some(Fault::TooManyRequests { retry_after_sec }) => return HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after_sec.to_string()))
+            .finish(),
+        Some(Fault::Delay(delay)) => tokio::time::sleep(delay).await,
+        None => {}
+    }
+
+    let mut state = state.lock().unwrap();
+    if form.client_id != state.client_id || form.client_secret != state.client_secret {
+        return oauth2_error("invalid_client", "Unknown client");
+    }
+
+    match form.grant_type.as_str() {
+        "authorization_code" => {
+            let code = match form.code.as_deref().and_then(|code| state.codes.remove(code)) {
+                Some(x) => x,
+                None => return oauth2_error("invalid_grant", "Unknown or used code"),
+            };
+
+            if form.redirect_uri.as_deref() != Some(code.redirect_uri.as_str()) {
+                return oauth2_error("invalid_grant", "The redirect_uri does not match");
+            }
+
+            HttpResponse::Ok().json(TokenResponse::from(state.issue_token_pair()))
+        },
+        "refresh_token" => {
+            let refresh_token = form.refresh_token.as_deref().unwrap_or_default();
+            let issued = match state.refresh_tokens.get(refresh_token) {
+                Some(x) => x,
+                None => return oauth2_error("invalid_grant", "Unknown or used refresh token"),
+            };
+
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+            if issued.access_expiry - now > REFRESH_WINDOW_SEC {
+                return oauth2_error("invalid_request", "The access token is not yet expired");
+            }
+
+            // Refresh tokens rotate, the used token is no longer valid
+            state.refresh_tokens.remove(refresh_token);
+            HttpResponse::Ok().json(TokenResponse::from(state.issue_token_pair()))
+        },
+        _ => oauth2_error("unsupported_grant_type", "Unsupported grant_type"),
+    }
+}
+
+/// Exact responds to an unknown client with `401 Unauthorized`, to any other error with `400 Bad Request`
+fn oauth2_error(error: &'static str, error_description: &'static str) -> HttpResponse {
+    let status = match error {
+        "invalid_client" => StatusCode::UNAUTHORIZED,
+        _ => StatusCode::BAD_REQUEST,
+    };
+
+    HttpResponse::build(status).json(ErrorResponse {
+        error,
+        error_description,
+    })
+}
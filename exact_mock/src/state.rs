@@ -0,0 +1,75 @@
+use std::collections::{HashMap, VecDeque};
+use actix_web::cookie::time;
+use rand::distributions::{Alphanumeric, DistString};
+use crate::Fault;
+
+/// Exact access tokens are valid for 10 minutes
+pub(crate) const DEFAULT_ACCESS_TOKEN_LIFETIME_SEC: i64 = 600;
+const TOKEN_LEN: usize = 32;
+
+/// An authorization code issued to a user who completed the login, not yet exchanged
+pub(crate) struct IssuedCode {
+    pub redirect_uri: String,
+}
+
+/// A refresh token which has not been used yet
+pub(crate) struct IssuedRefreshToken {
+    /// The expiry of the access token issued together with the refresh token
+    pub access_expiry: i64,
+}
+
+pub(crate) struct TokenPair {
+    pub access: String,
+    pub refresh: String,
+    pub expires_in: i64,
+}
+
+pub(crate) struct State {
+    pub client_id: String,
+    pub client_secret: String,
+    pub access_token_lifetime_sec: i64,
+    pub codes: HashMap<String, IssuedCode>,
+    pub refresh_tokens: HashMap<String, IssuedRefreshToken>,
+    pub faults: VecDeque<Fault>,
+    pub token_requests: usize,
+}
+
+impl State {
+    pub fn new(client_id: &str, client_secret: &str) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            client_secret: client_secret.to_string(),
+            access_token_lifetime_sec: DEFAULT_ACCESS_TOKEN_LIFETIME_SEC,
+            codes: HashMap::new(),
+            refresh_tokens: HashMap::new(),
+            faults: VecDeque::new(),
+            token_requests: 0,
+        }
+    }
+
+    pub fn issue_code(&mut self, redirect_uri: &str) -> String {
+        let code = generate_token();
+        self.codes.insert(code.clone(), IssuedCode {
+            redirect_uri: redirect_uri.to_string(),
+        });
+        code
+    }
+
+    pub fn issue_token_pair(&mut self) -> TokenPair {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let pair = TokenPair {
+            access: generate_token(),
+            refresh: generate_token(),
+            expires_in: self.access_token_lifetime_sec,
+        };
+
+        self.refresh_tokens.insert(pair.refresh.clone(), IssuedRefreshToken {
+            access_expiry: now + pair.expires_in,
+        });
+        pair
+    }
+}
+
+fn generate_token() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), TOKEN_LEN)
+}
@@ -0,0 +1,110 @@
+use std::str::FromStr;
+use thiserror::Error;
+use url::Url;
+
+/// The callers users may be redirected back to after logging in with Exact
+pub struct AllowedCallers(Vec<AllowedCaller>);
+
+enum AllowedCaller {
+    /// Any URL on exactly this origin, e.g. `https://app.example.com`
+    Origin(Url),
+    /// Any URL on a subdomain of the domain, e.g. `https://*.example.com`
+    WildcardSubdomain {
+        scheme: String,
+        domain: String,
+        port: Option<u16>,
+    },
+    /// Exactly this URL, apart from its query and fragment, e.g. `https://app.example.com/exact/callback`
+    RedirectUri(Url),
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid allowed caller '{0}': {1}")]
+pub struct InvalidAllowedCaller(String, String);
+
+impl FromStr for AllowedCallers {
+    type Err = InvalidAllowedCaller;
+
+    /// Parse a comma separated list of origins, wildcard subdomains and redirect URIs
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let callers = s.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(AllowedCaller::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self(callers))
+    }
+}
+
+impl AllowedCallers {
+    /// Whether the user may be redirected to `caller`
+    pub fn is_allowed(&self, caller: &str) -> bool {
+        let caller = match Url::parse(caller) {
+            Ok(x) => x,
+            Err(_) => return false,
+        };
+
+        if !matches!(caller.scheme(), "http" | "https")
+            || !caller.username().is_empty()
+            || caller.password().is_some()
+        {
+            return false;
+        }
+
+        self.0.iter().any(|allowed| allowed.matches(&caller))
+    }
+}
+
+impl AllowedCaller {
+    fn parse(entry: &str) -> Result<Self, InvalidAllowedCaller> {
+        let invalid = |reason: &str| InvalidAllowedCaller(entry.to_string(), reason.to_string());
+
+        if let Some((scheme, rest)) = entry.split_once("://*.") {
+            let url = Url::parse(&format!("{scheme}://{rest}"))
+                .map_err(|e| invalid(&e.to_string()))?;
+            if url.path() != "/" {
+                return Err(invalid("Wildcard subdomains may not have a path"));
+            }
+
+            return Ok(Self::WildcardSubdomain {
+                scheme: url.scheme().to_string(),
+                domain: url.host_str().ok_or_else(|| invalid("Missing host"))?.to_string(),
+                port: url.port_or_known_default(),
+            });
+        }
+
+        let mut url = Url::parse(entry)
+            .map_err(|e| invalid(&e.to_string()))?;
+        if url.host_str().is_none() {
+            return Err(invalid("Missing host"));
+        }
+
+        url.set_query(None);
+        url.set_fragment(None);
+        if url.path() == "/" {
+            Ok(Self::Origin(url))
+        } else {
+            Ok(Self::RedirectUri(url))
+        }
+    }
+
+    fn matches(&self, caller: &Url) -> bool {
+        match self {
+            Self::Origin(origin) => origin.origin() == caller.origin(),
+            Self::WildcardSubdomain { scheme, domain, port } => {
+                caller.scheme() == scheme
+                    && caller.port_or_known_default() == *port
+                    && caller.host_str()
+                        .map(|host| host.ends_with(&format!(".{domain}")))
+                        .unwrap_or(false)
+            },
+            Self::RedirectUri(uri) => {
+                let mut caller = caller.clone();
+                caller.set_query(None);
+                caller.set_fragment(None);
+                caller == *uri
+            }
+        }
+    }
+}
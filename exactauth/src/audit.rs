@@ -0,0 +1,78 @@
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+use actix_web::{FromRequest, HttpRequest};
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use tracing::warn;
+use dal::{AuditAction, AuditEvent, AuditOutcome, Database, NewAuditEvent};
+use crate::TrustedProxiesData;
+use crate::trusted_proxies::TrustedProxies;
+
+/// The client a request was made by, as recorded in the audit log
+pub struct ClientInfo {
+    /// The IP address of the client. `X-Forwarded-For` is only honoured as far as it was set by the configured trusted proxies
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequest for ClientInfo {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(Self::from_http_request(req)))
+    }
+}
+
+impl ClientInfo {
+    /// The client which made the request
+    pub fn from_http_request(req: &HttpRequest) -> Self {
+        let user_agent = req.headers().get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let ip = match req.app_data::<TrustedProxiesData>() {
+            Some(trusted_proxies) => trusted_proxies.client_ip(req),
+            None => TrustedProxies::default().client_ip(req),
+        };
+
+        Self {
+            ip: ip.map(|ip| ip.to_string()),
+            user_agent,
+        }
+    }
+
+    /// A successful `action` performed by `actor` from this client, concerning the tokens of `user_id`,
+    /// or `None` if the action does not concern the tokens of any user.
+    /// `connection_id` is `None` if it is not yet known which of the user's connections the action concerns
+    pub fn audit_event(&self, actor: &str, user_id: Option<&str>, connection_id: Option<&str>, action: AuditAction) -> NewAuditEvent {
+        NewAuditEvent {
+            actor: actor.to_string(),
+            user_id: user_id.map(str::to_string),
+            connection_id: connection_id.map(str::to_string),
+            grantee_id: None,
+            action,
+            outcome: AuditOutcome::Success,
+            detail: None,
+            ip: self.ip.clone(),
+            user_agent: self.user_agent.clone(),
+        }
+    }
+}
+
+/// Record an audit event as is.
+/// Failing to record the event is logged, but does not fail the action it records
+pub async fn record(db: &Database, event: NewAuditEvent) {
+    if let Err(e) = AuditEvent::create(db.clone(), &event).await {
+        warn!("Failed to record audit event {} by {}: {e}", event.action, event.actor);
+    }
+}
+
+/// Record the event as failed, with the reason it failed
+pub async fn record_failure(db: &Database, event: NewAuditEvent, detail: impl ToString) {
+    record(db, NewAuditEvent {
+        outcome: AuditOutcome::Failure,
+        detail: Some(detail.to_string()),
+        ..event
+    }).await
+}
@@ -1,13 +1,175 @@
 use serde::Deserialize;
+use thiserror::Error;
+use dal::DatabaseConfig;
+use crate::exact_api::{ClientCredentials, DEFAULT_AUTH_PATH, DEFAULT_TOKEN_PATH, ExactEndpoints};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
-    pub mysql_host: String,
-    pub mysql_user: String,
-    pub mysql_password: String,
-    pub mysql_db: String,
+    /// The database backend to use
+    #[serde(default)]
+    pub database_backend: DatabaseBackend,
+    pub mysql_host: Option<String>,
+    pub mysql_user: Option<String>,
+    pub mysql_password: Option<String>,
+    pub mysql_db: Option<String>,
+    pub postgres_host: Option<String>,
+    pub postgres_user: Option<String>,
+    pub postgres_password: Option<String>,
+    pub postgres_db: Option<String>,
+    /// Path to the SQLite database file, or `:memory:`
+    pub sqlite_path: Option<String>,
     pub exact_client_id: String,
     pub exact_client_secret: String,
     pub redirect_uri: String,
+    /// The URL ExactAuth is reachable at by users, e.g. `https://exactauth.example.com`
+    pub public_url: String,
+    /// Base URL used for Exact in every region instead of the region's Exact host, e.g. `http://localhost:9090`
+    pub exact_base_url: Option<String>,
+    /// Path of Exact's OAuth2 authorization endpoint
+    #[serde(default = "default_exact_auth_path")]
+    pub exact_auth_path: String,
+    /// Path of Exact's OAuth2 token endpoint
+    #[serde(default = "default_exact_token_path")]
+    pub exact_token_path: String,
     pub mrauth_url: String,
-}
\ No newline at end of file
+    /// Comma separated origins, wildcard subdomains and redirect URIs users may be redirected back to
+    pub allowed_callers: String,
+    /// Comma separated IP addresses of the reverse proxies whose `X-Forwarded-For` entries are trusted
+    #[serde(default)]
+    pub trusted_proxies: String,
+    /// ID of the key in `token_encryption_keys` new tokens are encrypted with
+    pub token_encryption_key_id: String,
+    /// Keys tokens are encrypted with, formatted as `<key id>:<base64 key>,<key id>:<base64 key>`
+    pub token_encryption_keys: String,
+    /// The maximum number of users whose tokens are refreshed concurrently
+    #[serde(default = "default_refresh_parallelism")]
+    pub refresh_parallelism: usize,
+    /// The number of seconds a user has to complete the Exact login after it was started
+    #[serde(default = "default_authorization_start_ttl_sec")]
+    pub authorization_start_ttl_sec: i64,
+    /// The number of seconds a login ticket may be used after it was created
+    #[serde(default = "default_login_ticket_ttl_sec")]
+    pub login_ticket_ttl_sec: i64,
+    /// The number of seconds to wait for a connection to Exact
+    #[serde(default = "default_exact_connect_timeout_sec")]
+    pub exact_connect_timeout_sec: u64,
+    /// The number of seconds to wait for a complete response from Exact, for requests which are not proxied
+    #[serde(default = "default_exact_timeout_sec")]
+    pub exact_timeout_sec: u64,
+    /// The number of seconds a proxied request to Exact may go without receiving anything from Exact
+    #[serde(default = "default_exact_idle_timeout_sec")]
+    pub exact_idle_timeout_sec: u64,
+    /// The number of times a request to Exact that failed transiently is retried
+    #[serde(default = "default_exact_max_retries")]
+    pub exact_max_retries: u32,
+    /// The backoff before the first retry of a request to Exact, doubled for every following retry
+    #[serde(default = "default_exact_retry_base_delay_millis")]
+    pub exact_retry_base_delay_millis: u64,
+    /// The maximum backoff between retries of a request to Exact
+    #[serde(default = "default_exact_retry_max_delay_millis")]
+    pub exact_retry_max_delay_millis: u64,
+}
+
+fn default_refresh_parallelism() -> usize {
+    8
+}
+
+fn default_authorization_start_ttl_sec() -> i64 {
+    600
+}
+
+fn default_login_ticket_ttl_sec() -> i64 {
+    60
+}
+
+fn default_exact_auth_path() -> String {
+    DEFAULT_AUTH_PATH.to_string()
+}
+
+fn default_exact_token_path() -> String {
+    DEFAULT_TOKEN_PATH.to_string()
+}
+
+fn default_exact_connect_timeout_sec() -> u64 {
+    5
+}
+
+fn default_exact_timeout_sec() -> u64 {
+    30
+}
+
+fn default_exact_idle_timeout_sec() -> u64 {
+    30
+}
+
+fn default_exact_max_retries() -> u32 {
+    3
+}
+
+fn default_exact_retry_base_delay_millis() -> u64 {
+    500
+}
+
+fn default_exact_retry_max_delay_millis() -> u64 {
+    10_000
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DatabaseBackend {
+    #[default]
+    Mysql,
+    Postgres,
+    Sqlite,
+}
+
+#[derive(Debug, Error)]
+#[error("Missing configuration value '{0}' for the configured database backend")]
+pub struct MissingConfig(&'static str);
+
+impl Config {
+    /// The configuration of the database of the configured backend
+    pub fn database_config(&self) -> Result<DatabaseConfig, MissingConfig> {
+        let config = match self.database_backend {
+            DatabaseBackend::Mysql => DatabaseConfig::Mysql {
+                host: require(&self.mysql_host, "MYSQL_HOST")?,
+                user: require(&self.mysql_user, "MYSQL_USER")?,
+                password: require(&self.mysql_password, "MYSQL_PASSWORD")?,
+                database: require(&self.mysql_db, "MYSQL_DB")?,
+            },
+            DatabaseBackend::Postgres => DatabaseConfig::Postgres {
+                host: require(&self.postgres_host, "POSTGRES_HOST")?,
+                user: require(&self.postgres_user, "POSTGRES_USER")?,
+                password: require(&self.postgres_password, "POSTGRES_PASSWORD")?,
+                database: require(&self.postgres_db, "POSTGRES_DB")?,
+            },
+            DatabaseBackend::Sqlite => DatabaseConfig::Sqlite {
+                path: require(&self.sqlite_path, "SQLITE_PATH")?,
+            },
+        };
+
+        Ok(config)
+    }
+
+    /// The OAuth2 client ExactAuth is registered as at Exact
+    pub fn exact_credentials(&self) -> ClientCredentials {
+        ClientCredentials {
+            client_id: self.exact_client_id.clone(),
+            client_secret: self.exact_client_secret.clone(),
+            redirect_uri: self.redirect_uri.clone(),
+        }
+    }
+
+    /// Where Exact is reached
+    pub fn exact_endpoints(&self) -> ExactEndpoints {
+        ExactEndpoints {
+            base_url: self.exact_base_url.clone(),
+            auth_path: self.exact_auth_path.clone(),
+            token_path: self.exact_token_path.clone(),
+        }
+    }
+}
+
+fn require(value: &Option<String>, name: &'static str) -> Result<String, MissingConfig> {
+    value.clone().ok_or(MissingConfig(name))
+}
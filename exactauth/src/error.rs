@@ -1,8 +1,11 @@
 use actix_web::http::StatusCode;
 use actix_web::{HttpResponse, ResponseError};
 use actix_web::body::BoxBody;
+use std::sync::Arc;
 use thiserror::Error;
-use crate::exact_api::TokenError;
+use tracing::error;
+use crate::exact_api::{MeError, OAuth2ErrorCode, StreamingError, TokenError};
+use crate::refresher::RefreshError;
 
 pub type WebResult<T> = Result<T, Error>;
 
@@ -12,16 +15,28 @@ pub enum Error {
     Dal(#[from] dal::Error),
     #[error("Forbidden: {0}")]
     Forbidden(String),
+    #[error("Bad request: {0}")]
+    BadRequest(String),
     #[error("Error at upstream partner")]
     Reqwest(#[from] reqwest::Error),
     #[error("Token exchange error: {0}")]
     TokenExchangeError(#[from] TokenError),
+    #[error("Exact API error: {0}")]
+    ExactApi(#[from] MeError),
+    #[error("Error at upstream partner: {0}")]
+    Streaming(#[from] StreamingError),
+    #[error("Token refresh error: {0}")]
+    Refresh(#[from] Arc<RefreshError>),
     #[error("Authorization error: {0}")]
     AuthClient(#[from] mrauth::Error),
     #[error("Authorization failed")]
     AuthError(#[from] mrauth::actix::AuthError),
     #[error("Not found")]
-    NotFound
+    NotFound,
+    #[error("Reconnect with broader scopes, missing: {}", .0.join(" "))]
+    InsufficientScopes(Vec<String>),
+    #[error("Conflict: {0}")]
+    Conflict(String),
 }
 
 impl ResponseError for Error {
@@ -30,11 +45,20 @@ impl ResponseError for Error {
         match self {
             Self::Dal(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
             Self::Reqwest(_) => StatusCode::BAD_GATEWAY,
-            Self::TokenExchangeError(e) => match e {
-                TokenError::Reqwest(_) => StatusCode::BAD_GATEWAY,
-                TokenError::InvalidGrant => StatusCode::BAD_REQUEST,
-                TokenError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::TokenExchangeError(e) => token_error_status_code(e),
+            Self::ExactApi(_) => StatusCode::BAD_GATEWAY,
+            Self::Streaming(e) => match e {
+                StreamingError::Reqwest(_) => StatusCode::BAD_GATEWAY,
+                StreamingError::IdleTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            },
+            Self::Refresh(e) => match e.as_ref() {
+                RefreshError::Dal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                RefreshError::Token(e) => token_error_status_code(e),
+                RefreshError::ReauthorizationRequired => StatusCode::CONFLICT,
+                RefreshError::Timeout => StatusCode::SERVICE_UNAVAILABLE,
+                RefreshError::Task(_) => StatusCode::INTERNAL_SERVER_ERROR,
             },
             Self::AuthClient(e) => match e {
                 mrauth::Error::Reqwest(_) => StatusCode::BAD_GATEWAY,
@@ -46,14 +70,43 @@ impl ResponseError for Error {
             },
             Self::AuthError(e) => e.status_code(),
             Self::NotFound => StatusCode::NOT_FOUND,
+            // Like a required reauthorization, the user must log in again
+            Self::InsufficientScopes(_) => StatusCode::CONFLICT,
+            Self::Conflict(_) => StatusCode::CONFLICT,
         }
     }
 
     fn error_response(&self) -> HttpResponse<BoxBody> {
         match self {
             Self::AuthError(e) => e.error_response(),
+            // Storage and task errors are not exposed, like `Self::Dal`
+            Self::Refresh(e) if matches!(e.as_ref(), RefreshError::Dal(_) | RefreshError::Task(_)) => {
+                error!("Token refresh failed: {e}");
+                HttpResponse::build(self.status_code()).body("Internal server error")
+            },
             _ => HttpResponse::build(self.status_code()).body(self.to_string().into_bytes())
         }
     }
 
-}
\ No newline at end of file
+}
+
+fn token_error_status_code(e: &TokenError) -> StatusCode {
+    match e {
+        TokenError::Reqwest(_) => StatusCode::BAD_GATEWAY,
+        TokenError::OAuth2(e) => match e.code {
+            // The code or refresh token presented by the user is no longer valid
+            OAuth2ErrorCode::InvalidGrant
+            | OAuth2ErrorCode::InvalidScope => StatusCode::BAD_REQUEST,
+            // ExactAuth is misconfigured, or sent a request Exact does not accept
+            OAuth2ErrorCode::InvalidRequest
+            | OAuth2ErrorCode::InvalidClient
+            | OAuth2ErrorCode::UnauthorizedClient
+            | OAuth2ErrorCode::UnsupportedGrantType => StatusCode::INTERNAL_SERVER_ERROR,
+            OAuth2ErrorCode::TemporarilyUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            OAuth2ErrorCode::ServerError
+            | OAuth2ErrorCode::Unknown(_) => StatusCode::BAD_GATEWAY,
+        },
+        TokenError::UnexpectedResponse { .. }
+        | TokenError::InvalidResponse(_) => StatusCode::BAD_GATEWAY,
+    }
+}
@@ -0,0 +1,196 @@
+use std::error::Error as StdError;
+use std::io;
+use std::io::ErrorKind;
+use std::time::{Duration, Instant, SystemTime};
+use actix_web::web::Bytes;
+use futures::{Stream, StreamExt};
+use rand::Rng;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use reqwest::header::RETRY_AFTER;
+use thiserror::Error;
+use tracing::warn;
+use crate::exact_api::ExactEndpoints;
+
+#[derive(Debug, Error)]
+pub enum StreamingError {
+    #[error("Request error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    /// Exact sent nothing for longer than the idle timeout
+    #[error("Exact did not respond within {} seconds", .0.as_secs())]
+    IdleTimeout(Duration),
+}
+
+/// When to retry requests to Exact that failed transiently
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The number of times a request is retried, after the first attempt
+    pub max_retries: u32,
+    /// The backoff before the first retry, doubled for every following retry
+    pub base_delay: Duration,
+    /// The maximum backoff. A `Retry-After` longer than this is not waited for, the request fails instead
+    pub max_delay: Duration,
+}
+
+/// HTTP client for all requests to Exact.
+/// Shared, so that connections to Exact are reused
+#[derive(Clone)]
+pub struct ExactClient {
+    client: Client,
+    /// The maximum duration of a single attempt of a request which is not streamed
+    timeout: Duration,
+    /// The maximum time a streamed request may go without receiving anything from Exact
+    idle_timeout: Duration,
+    retry_policy: RetryPolicy,
+    endpoints: ExactEndpoints,
+}
+
+impl ExactClient {
+    /// `timeout` bounds every attempt of a request sent with [Self::send] or [Self::send_non_idempotent],
+    /// from connecting until the response body is read.
+    /// Streamed requests are not bounded as a whole, only by `idle_timeout` between receiving anything from Exact
+    pub fn new(endpoints: ExactEndpoints, connect_timeout: Duration, timeout: Duration, idle_timeout: Duration, retry_policy: RetryPolicy) -> reqwest::Result<Self> {
+        let client = Client::builder()
+            .user_agent(format!("MrFriendly {} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")))
+            .connect_timeout(connect_timeout)
+            .build()?;
+
+        Ok(Self {
+            client,
+            timeout,
+            idle_timeout,
+            retry_policy,
+            endpoints,
+        })
+    }
+
+    pub fn endpoints(&self) -> &ExactEndpoints {
+        &self.endpoints
+    }
+
+    /// The underlying HTTP client, to build requests with.
+    /// It only has a connect timeout, send requests with one of the `send` methods to apply the other timeouts
+    pub fn http(&self) -> &Client {
+        &self.client
+    }
+
+    /// Send a request once, without retries, of which the response may be streamed for as long as it takes.
+    /// Waiting for the response is bounded by the idle timeout, read its body with [Self::stream_body]
+    pub async fn send_streaming(&self, request: RequestBuilder) -> Result<Response, StreamingError> {
+        tokio::time::timeout(self.idle_timeout, request.send()).await
+            .map_err(|_| StreamingError::IdleTimeout(self.idle_timeout))?
+            .map_err(StreamingError::from)
+    }
+
+    /// The body of a response, which ends with an error if no chunk arrives within the idle timeout
+    pub fn stream_body(&self, response: Response) -> impl Stream<Item = Result<Bytes, io::Error>> + 'static {
+        let idle_timeout = self.idle_timeout;
+        futures::stream::unfold(Some(response.bytes_stream().boxed()), move |body| async move {
+            let mut body = body?;
+            match tokio::time::timeout(idle_timeout, body.next()).await {
+                Ok(Some(Ok(chunk))) => Some((Ok(chunk), Some(body))),
+                Ok(Some(Err(e))) => Some((Err(io::Error::new(ErrorKind::Other, e)), None)),
+                Ok(None) => None,
+                Err(_) => Some((Err(io::Error::new(ErrorKind::TimedOut, StreamingError::IdleTimeout(idle_timeout))), None)),
+            }
+        })
+    }
+
+    /// Send an idempotent request, retrying it if it failed transiently: on a 5xx or 429 response, or if the connection failed or was reset.
+    /// Timeouts are not retried, a slow Exact would only be loaded further.
+    /// Requests of which the body can not be cloned are sent only once
+    pub async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        self.send_retrying(request, true, None).await
+    }
+
+    /// Send a request which may change state at Exact, e.g. a token request which rotates the refresh token.
+    /// It is only retried if Exact can not have handled it: if connecting failed, or on a 5xx or 429 response.
+    /// A reset connection or a timeout may come after Exact handled the request, retrying would then
+    /// present a refresh token which was already used.
+    ///
+    /// If a `deadline` is provided, every attempt is cut off at the deadline, and no retry is started that would wait past it
+    pub async fn send_non_idempotent(&self, request: RequestBuilder, deadline: Option<Instant>) -> reqwest::Result<Response> {
+        self.send_retrying(request, false, deadline).await
+    }
+
+    async fn send_retrying(&self, request: RequestBuilder, idempotent: bool, deadline: Option<Instant>) -> reqwest::Result<Response> {
+        let mut attempt = 0;
+        loop {
+            let current = match request.try_clone() {
+                Some(x) => x,
+                None => return request.timeout(self.timeout).send().await,
+            };
+
+            let current = match deadline {
+                Some(deadline) => current.timeout(self.timeout.min(deadline.saturating_duration_since(Instant::now()))),
+                None => current.timeout(self.timeout),
+            };
+
+            let can_retry = attempt < self.retry_policy.max_retries;
+            let outcome = current.send().await;
+            let delay = match &outcome {
+                Ok(response) if can_retry && is_transient_status(response.status()) => match retry_after(response) {
+                    Some(retry_after) if retry_after > self.retry_policy.max_delay => None,
+                    Some(retry_after) => Some(retry_after),
+                    None => Some(self.backoff(attempt)),
+                },
+                Err(e) if can_retry && (e.is_connect() || (idempotent && is_connection_reset(e))) => Some(self.backoff(attempt)),
+                _ => None,
+            };
+
+            let delay = match delay {
+                Some(delay) if deadline.map_or(true, |deadline| Instant::now() + delay < deadline) => delay,
+                _ => return outcome,
+            };
+
+            match &outcome {
+                Ok(response) => warn!("Exact responded with status {}, retrying in {} ms", response.status(), delay.as_millis()),
+                Err(e) => warn!("Request to Exact failed: {e}, retrying in {} ms", delay.as_millis()),
+            }
+
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    /// Exponential backoff with full jitter, so that instances retrying at the same time spread out
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self.retry_policy.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.retry_policy.max_delay);
+        let jittered_millis = rand::thread_rng().gen_range(0..=ceiling.as_millis() as u64);
+        Duration::from_millis(jittered_millis)
+    }
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Whether the connection was reset or aborted after it was established
+fn is_connection_reset(e: &reqwest::Error) -> bool {
+    if e.is_timeout() {
+        return false;
+    }
+
+    let mut source = e.source();
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<std::io::Error>() {
+            return matches!(e.kind(), ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe);
+        }
+        source = e.source();
+    }
+
+    false
+}
+
+/// The delay requested by the `Retry-After` header, either in seconds or as an HTTP date
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let at = httpdate::parse_http_date(value).ok()?;
+    // A date in the past means the request may be retried right away
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
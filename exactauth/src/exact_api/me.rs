@@ -0,0 +1,80 @@
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::instrument;
+use dal::Region;
+use crate::exact_api::ExactClient;
+
+pub const ME_PATH: &str = "/api/v1/current/Me";
+
+#[derive(Debug, Error)]
+pub enum MeError {
+    #[error("Request error: {0}")]
+    Reqwest(#[from] reqwest::Error),
+    #[error("Exact returned no current user")]
+    Empty,
+}
+
+pub struct Me {
+    pub user_id: String,
+    pub full_name: String,
+    pub email: String,
+    pub current_division: i64,
+}
+
+impl From<Me> for dal::ExactUser {
+    fn from(x: Me) -> Self {
+        Self {
+            exact_user_id: x.user_id,
+            full_name: x.full_name,
+            email: x.email,
+            current_division: x.current_division,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ResponseJson {
+    d: ResponseData,
+}
+
+#[derive(Deserialize)]
+struct ResponseData {
+    results: Vec<MeJson>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MeJson {
+    #[serde(rename = "UserID")]
+    user_id: String,
+    full_name: String,
+    email: String,
+    current_division: i64,
+}
+
+/// Retrieve the Exact user the access token belongs to
+#[instrument(skip_all)]
+pub async fn get_me(client: &ExactClient, region: Region, access_token: &str) -> Result<Me, MeError> {
+    let request = client.http()
+        .get(client.endpoints().url(region, ME_PATH))
+        .query(&[("$select", "UserID,FullName,Email,CurrentDivision")])
+        .header("Accept", "application/json")
+        .bearer_auth(access_token);
+    let response: ResponseJson = client.send(request)
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    let me = response.d.results
+        .into_iter()
+        .next()
+        .ok_or(MeError::Empty)?;
+
+    Ok(Me {
+        user_id: me.user_id,
+        full_name: me.full_name,
+        email: me.email,
+        current_division: me.current_division,
+    })
+}
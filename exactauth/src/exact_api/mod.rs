@@ -1,8 +1,74 @@
+use dal::Region;
+
+mod client;
+pub use client::*;
+
 mod token;
 pub use token::*;
 
+mod oauth2_error;
+pub use oauth2_error::*;
+
+mod me;
+pub use me::*;
+
 const REGION_NL_BASE: &str = "https://start.exactonline.nl";
+const REGION_BE_BASE: &str = "https://start.exactonline.be";
+const REGION_UK_BASE: &str = "https://start.exactonline.co.uk";
+const REGION_DE_BASE: &str = "https://start.exactonline.de";
+const REGION_US_BASE: &str = "https://start.exactonline.com";
+const REGION_ES_BASE: &str = "https://start.exactonline.es";
+const REGION_FR_BASE: &str = "https://start.exactonline.fr";
+
+fn get_region_base(region: Region) -> &'static str {
+    match region {
+        Region::Nl => REGION_NL_BASE,
+        Region::Be => REGION_BE_BASE,
+        Region::Uk => REGION_UK_BASE,
+        Region::De => REGION_DE_BASE,
+        Region::Us => REGION_US_BASE,
+        Region::Es => REGION_ES_BASE,
+        Region::Fr => REGION_FR_BASE,
+    }
+}
+
+/// Path of Exact's OAuth2 authorization endpoint
+pub const DEFAULT_AUTH_PATH: &str = "/api/oauth2/auth";
+/// Path of Exact's OAuth2 token endpoint
+pub const DEFAULT_TOKEN_PATH: &str = "/api/oauth2/token";
+
+/// Where Exact is reached
+#[derive(Debug, Clone)]
+pub struct ExactEndpoints {
+    /// Used for every region instead of the region's Exact host, e.g. to use a local mock or an Exact test environment
+    pub base_url: Option<String>,
+    pub auth_path: String,
+    pub token_path: String,
+}
+
+impl Default for ExactEndpoints {
+    fn default() -> Self {
+        Self {
+            base_url: None,
+            auth_path: DEFAULT_AUTH_PATH.to_string(),
+            token_path: DEFAULT_TOKEN_PATH.to_string(),
+        }
+    }
+}
+
+impl ExactEndpoints {
+    pub fn url(&self, region: Region, path: &str) -> String {
+        match &self.base_url {
+            Some(base_url) => format!("{}{path}", base_url.trim_end_matches('/')),
+            None => format!("{}{path}", get_region_base(region)),
+        }
+    }
+
+    pub fn auth_url(&self, region: Region) -> String {
+        self.url(region, &self.auth_path)
+    }
 
-pub fn get_exact_url(path: &str) -> String {
-    format!("{REGION_NL_BASE}{path}")
-}
\ No newline at end of file
+    pub fn token_url(&self, region: Region) -> String {
+        self.url(region, &self.token_path)
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::{Display, Formatter};
+
+/// An OAuth2 error code, as returned in the `error` field of an error response.
+/// See [RFC 6749 section 5.2](https://www.rfc-editor.org/rfc/rfc6749#section-5.2)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OAuth2ErrorCode {
+    /// The request is missing a parameter, or is otherwise malformed
+    InvalidRequest,
+    /// Client authentication failed, e.g. the client ID or secret is wrong
+    InvalidClient,
+    /// The authorization code or refresh token is invalid, expired or revoked
+    InvalidGrant,
+    /// The client is not authorized to use this grant type
+    UnauthorizedClient,
+    UnsupportedGrantType,
+    /// The requested scope is invalid, unknown or exceeds the scope granted by the resource owner
+    InvalidScope,
+    /// Defined for the authorization endpoint, but returned by Exact's token endpoint as well when it fails
+    ServerError,
+    /// Defined for the authorization endpoint, but returned by Exact's token endpoint as well when it is overloaded
+    TemporarilyUnavailable,
+    /// An error code not defined by RFC 6749
+    Unknown(String),
+}
+
+impl OAuth2ErrorCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::InvalidRequest => "invalid_request",
+            Self::InvalidClient => "invalid_client",
+            Self::InvalidGrant => "invalid_grant",
+            Self::UnauthorizedClient => "unauthorized_client",
+            Self::UnsupportedGrantType => "unsupported_grant_type",
+            Self::InvalidScope => "invalid_scope",
+            Self::ServerError => "server_error",
+            Self::TemporarilyUnavailable => "temporarily_unavailable",
+            Self::Unknown(code) => code,
+        }
+    }
+}
+
+impl From<&str> for OAuth2ErrorCode {
+    fn from(code: &str) -> Self {
+        match code {
+            "invalid_request" => Self::InvalidRequest,
+            "invalid_client" => Self::InvalidClient,
+            "invalid_grant" => Self::InvalidGrant,
+            "unauthorized_client" => Self::UnauthorizedClient,
+            "unsupported_grant_type" => Self::UnsupportedGrantType,
+            "invalid_scope" => Self::InvalidScope,
+            "server_error" => Self::ServerError,
+            "temporarily_unavailable" => Self::TemporarilyUnavailable,
+            _ => Self::Unknown(code.to_string()),
+        }
+    }
+}
+
+impl Display for OAuth2ErrorCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// An OAuth2 error response
+#[derive(Debug)]
+pub struct OAuth2Error {
+    pub code: OAuth2ErrorCode,
+    /// Human-readable explanation provided by Exact, if any
+    pub description: Option<String>,
+    /// The HTTP status code of the response
+    pub status: u16,
+    /// The response body as received, for diagnostics
+    pub body: String,
+}
+
+impl Display for OAuth2Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.description {
+            Some(description) => write!(f, "{}: {description}", self.code),
+            None => write!(f, "{}", self.code),
+        }
+    }
+}
@@ -1,21 +1,45 @@
 use std::str::FromStr;
+use std::time::Instant;
 use actix_web::cookie::time;
-use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tracing::instrument;
-use crate::exact_api::get_exact_url;
-
-pub const TOKEN_PATH: &str = "/api/oauth2/token";
+use tracing::{debug, instrument};
+use dal::Region;
+use crate::exact_api::{ExactClient, OAuth2Error, OAuth2ErrorCode};
 
 #[derive(Debug, Error)]
 pub enum TokenError {
     #[error("Request error: {0}")]
     Reqwest(#[from] reqwest::Error),
-    #[error("Invalid grant: Either the token/code is invalid or has expired")]
-    InvalidGrant,
-    #[error("An OAuth2 error occurred: {0}")]
-    Other(String)
+    /// Exact returned an OAuth2 error response
+    #[error("OAuth2 error: {0}")]
+    OAuth2(OAuth2Error),
+    /// Exact returned an error response which is not an OAuth2 error response, e.g. an HTML error page
+    #[error("Unexpected response from Exact with status {status}")]
+    UnexpectedResponse {
+        status: u16,
+        /// The response body as received, for diagnostics
+        body: String,
+    },
+    /// Exact returned a successful response which could not be parsed
+    #[error("Invalid token response: {0}")]
+    InvalidResponse(String),
+}
+
+impl TokenError {
+    /// The OAuth2 error code Exact returned, if it returned an OAuth2 error response
+    pub fn oauth2_code(&self) -> Option<&OAuth2ErrorCode> {
+        match self {
+            Self::OAuth2(e) => Some(&e.code),
+            _ => None,
+        }
+    }
+
+    /// Whether the authorization code or refresh token is invalid, expired or revoked.
+    /// Retrying will not help, the user must log in again
+    pub fn is_invalid_grant(&self) -> bool {
+        self.oauth2_code() == Some(&OAuth2ErrorCode::InvalidGrant)
+    }
 }
 
 #[derive(Serialize, Clone)]
@@ -34,24 +58,35 @@ pub struct TokenPair {
 
 pub const REFRESH_VALID_FOR_SEC: i64 = 3600 * 24 * 30; //30 days
 
+/// The OAuth2 client ExactAuth is registered as at Exact
+#[derive(Debug, Clone)]
+pub struct ClientCredentials {
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_uri: String,
+}
+
 #[instrument(skip_all)]
-pub async fn exchange_code_for_token(client_id: &str, client_secret: &str, redirect_uri: &str, code: &str) -> Result<TokenPair, TokenError> {
+pub async fn exchange_code_for_token(client: &ExactClient, credentials: &ClientCredentials, region: Region, code: &str) -> Result<TokenPair, TokenError> {
     token_exchange(
-        client_id,
-        client_secret,
-        redirect_uri,
+        client,
+        credentials,
+        None,
+        region,
         Some(code),
         None,
         OAuth2GrantType::AuthorizationCode,
     ).await
 }
 
+/// Exchange the refresh token for a new token pair, giving up at `deadline`
 #[instrument(skip_all)]
-pub async fn refresh_tokens(client_id: &str, client_secret: &str, redirect_uri: &str, refresh_token: &str) -> Result<TokenPair, TokenError> {
+pub async fn refresh_tokens(client: &ExactClient, credentials: &ClientCredentials, deadline: Instant, region: Region, refresh_token: &str) -> Result<TokenPair, TokenError> {
     token_exchange(
-        client_id,
-        client_secret,
-        redirect_uri,
+        client,
+        credentials,
+        Some(deadline),
+        region,
         None,
         Some(refresh_token),
         OAuth2GrantType::RefreshToken,
@@ -80,42 +115,218 @@ struct ResponseJson {
 #[derive(Deserialize)]
 struct ErrorResponseJson {
     error: String,
+    error_description: Option<String>,
 }
 
 #[instrument(skip_all)]
-async fn token_exchange(client_id: &str, client_secret: &str, redirect_uri: &str, code: Option<&str>, refresh_token: Option<&str>, grant_type: OAuth2GrantType) -> Result<TokenPair, TokenError> {
-    let response = Client::new()
-        .post(get_exact_url(TOKEN_PATH))
-        .header("User-Agent", &format!("MrFriendly {} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")))
+async fn token_exchange(client: &ExactClient, credentials: &ClientCredentials, deadline: Option<Instant>, region: Region, code: Option<&str>, refresh_token: Option<&str>, grant_type: OAuth2GrantType) -> Result<TokenPair, TokenError> {
+    let request = client.http()
+        .post(client.endpoints().token_url(region))
         .form(&RequestForm {
-            redirect_uri,
+            redirect_uri: &credentials.redirect_uri,
             grant_type,
-            client_id,
-            client_secret,
+            client_id: &credentials.client_id,
+            client_secret: &credentials.client_secret,
             refresh_token,
             code
-        })
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        let error: ErrorResponseJson = response.json().await?;
-        return Err(if error.error.eq("invalid_grant") {
-            TokenError::InvalidGrant
-        } else {
-            TokenError::Other(error.error)
-        })
+        });
+    // Every token request changes state at Exact: codes are single use and refresh tokens rotate
+    let response = client.send_non_idempotent(request, deadline).await?;
+
+    let status = response.status();
+    let body = response.text().await?;
+
+    if !status.is_success() {
+        debug!("Exact token endpoint responded with status {status}: {body}");
+
+        // Exact does not always respond with JSON, e.g. when it is down for maintenance
+        return Err(match serde_json::from_str::<ErrorResponseJson>(&body) {
+            Ok(error) => TokenError::OAuth2(OAuth2Error {
+                code: OAuth2ErrorCode::from(error.error.as_str()),
+                description: error.error_description,
+                status: status.as_u16(),
+                body,
+            }),
+            Err(_) => TokenError::UnexpectedResponse {
+                status: status.as_u16(),
+                body,
+            }
+        });
     }
 
-    let response: ResponseJson = response.json().await?;
+    let response: ResponseJson = serde_json::from_str(&body)
+        .map_err(|e| TokenError::InvalidResponse(e.to_string()))?;
 
     let now = time::OffsetDateTime::now_utc().unix_timestamp();
     let expires_in = i64::from_str(&response.expires_in)
-        .map_err(|_| TokenError::Other(format!("Invalid value for 'expires_in', failed to parse '{}' to i64", response.expires_in)))?;
+        .map_err(|_| TokenError::InvalidResponse(format!("Invalid value for 'expires_in', failed to parse '{}' to i64", response.expires_in)))?;
     Ok(TokenPair {
         access: response.access_token,
         refresh: response.refresh_token,
         access_expiry: now + expires_in,
         refresh_expiry: now + REFRESH_VALID_FOR_SEC,
     })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use exact_mock::{Fault, MockExact};
+    use reqwest::redirect::Policy;
+    use crate::test_util::{CLIENT_ID, CLIENT_SECRET, credentials, exact_client, REDIRECT_URI};
+    use super::*;
+
+    async fn exchange(client: &ExactClient, code: &str) -> Result<TokenPair, TokenError> {
+        exchange_code_for_token(client, &credentials(), Region::Nl, code).await
+    }
+
+    async fn refresh(client: &ExactClient, refresh_token: &str) -> Result<TokenPair, TokenError> {
+        refresh_tokens(client, &credentials(), Instant::now() + Duration::from_secs(10), Region::Nl, refresh_token).await
+    }
+
+    #[tokio::test]
+    async fn exchanges_code_from_login() {
+        let mock = MockExact::start(CLIENT_ID, CLIENT_SECRET).await.unwrap();
+        let client = exact_client(&mock, 0, Duration::from_secs(5));
+
+        // Log in like a browser would, without following the redirect back to ExactAuth
+        let browser = reqwest::Client::builder().redirect(Policy::none()).build().unwrap();
+        let response = browser.get(client.endpoints().auth_url(Region::Nl))
+            .query(&[("client_id", CLIENT_ID), ("redirect_uri", REDIRECT_URI), ("response_type", "code"), ("state", "abc")])
+            .send()
+            .await
+            .unwrap();
+        let location = url::Url::parse(response.headers()["Location"].to_str().unwrap()).unwrap();
+        let query = location.query_pairs().collect::<HashMap<_, _>>();
+        assert_eq!(query["state"], "abc");
+
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        let pair = exchange(&client, &query["code"]).await.unwrap();
+        assert!((pair.access_expiry - now - 600).abs() <= 1);
+        assert!(mock.is_refresh_token_valid(&pair.refresh));
+
+        // Codes are single use
+        let e = exchange(&client, &query["code"]).await.err().unwrap();
+        assert!(e.is_invalid_grant());
+
+        mock.stop().await;
+    }
+
+    #[tokio::test]
+    async fn refresh_rotates_refresh_token() {
+        let mock = MockExact::start(CLIENT_ID, CLIENT_SECRET).await.unwrap();
+        mock.set_access_token_lifetime(20);
+        let client = exact_client(&mock, 0, Duration::from_secs(5));
+
+        let pair = exchange(&client, &mock.issue_code(REDIRECT_URI)).await.unwrap();
+        let refreshed = refresh(&client, &pair.refresh).await.unwrap();
+        assert_ne!(refreshed.refresh, pair.refresh);
+        assert!(mock.is_refresh_token_valid(&refreshed.refresh));
+
+        let e = refresh(&client, &pair.refresh).await.err().unwrap();
+        assert!(e.is_invalid_grant());
+
+        mock.stop().await;
+    }
+
+    #[tokio::test]
+    async fn refresh_before_window_is_rejected() {
+        let mock = MockExact::start(CLIENT_ID, CLIENT_SECRET).await.unwrap();
+        let client = exact_client(&mock, 0, Duration::from_secs(5));
+
+        let pair = exchange(&client, &mock.issue_code(REDIRECT_URI)).await.unwrap();
+        let e = refresh(&client, &pair.refresh).await.err().unwrap();
+        assert_eq!(e.oauth2_code(), Some(&OAuth2ErrorCode::InvalidRequest));
+        assert!(mock.is_refresh_token_valid(&pair.refresh));
+
+        mock.stop().await;
+    }
+
+    #[tokio::test]
+    async fn revoked_grant_is_invalid_grant() {
+        let mock = MockExact::start(CLIENT_ID, CLIENT_SECRET).await.unwrap();
+        mock.set_access_token_lifetime(20);
+        let client = exact_client(&mock, 3, Duration::from_secs(5));
+
+        let pair = exchange(&client, &mock.issue_code(REDIRECT_URI)).await.unwrap();
+        mock.push_fault(Fault::InvalidGrant);
+        let e = refresh(&client, &pair.refresh).await.err().unwrap();
+        assert!(e.is_invalid_grant());
+        // Client errors are not retried
+        assert_eq!(mock.token_requests(), 2);
+
+        mock.stop().await;
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let mock = MockExact::start(CLIENT_ID, CLIENT_SECRET).await.unwrap();
+        let client = exact_client(&mock, 3, Duration::from_secs(5));
+
+        mock.push_fault(Fault::ServerError(503));
+        mock.push_fault(Fault::ServerError(500));
+        exchange(&client, &mock.issue_code(REDIRECT_URI)).await.unwrap();
+        assert_eq!(mock.token_requests(), 3);
+
+        mock.stop().await;
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_retries() {
+        let mock = MockExact::start(CLIENT_ID, CLIENT_SECRET).await.unwrap();
+        let client = exact_client(&mock, 2, Duration::from_secs(5));
+
+        for _ in 0..3 {
+            mock.push_fault(Fault::ServerError(502));
+        }
+        let e = exchange(&client, &mock.issue_code(REDIRECT_URI)).await.err().unwrap();
+        assert!(matches!(e, TokenError::UnexpectedResponse { status: 502, .. }));
+        assert_eq!(mock.token_requests(), 3);
+
+        mock.stop().await;
+    }
+
+    #[tokio::test]
+    async fn honours_retry_after() {
+        let mock = MockExact::start(CLIENT_ID, CLIENT_SECRET).await.unwrap();
+        let client = exact_client(&mock, 1, Duration::from_secs(5));
+
+        mock.push_fault(Fault::TooManyRequests { retry_after_sec: 1 });
+        let started = Instant::now();
+        exchange(&client, &mock.issue_code(REDIRECT_URI)).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        assert_eq!(mock.token_requests(), 2);
+
+        mock.stop().await;
+    }
+
+    #[tokio::test]
+    async fn refresh_does_not_retry_past_deadline() {
+        let mock = MockExact::start(CLIENT_ID, CLIENT_SECRET).await.unwrap();
+        mock.set_access_token_lifetime(20);
+        let client = exact_client(&mock, 3, Duration::from_secs(5));
+
+        let pair = exchange(&client, &mock.issue_code(REDIRECT_URI)).await.unwrap();
+        mock.push_fault(Fault::TooManyRequests { retry_after_sec: 1 });
+        let deadline = Instant::now() + Duration::from_millis(500);
+        let e = refresh_tokens(&client, &credentials(), deadline, Region::Nl, &pair.refresh).await.err().unwrap();
+        assert!(matches!(e, TokenError::UnexpectedResponse { status: 429, .. }));
+        assert_eq!(mock.token_requests(), 2);
+
+        mock.stop().await;
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_timeouts() {
+        let mock = MockExact::start(CLIENT_ID, CLIENT_SECRET).await.unwrap();
+        let client = exact_client(&mock, 3, Duration::from_millis(200));
+
+        mock.push_fault(Fault::Delay(Duration::from_secs(1)));
+        let e = exchange(&client, &mock.issue_code(REDIRECT_URI)).await.err().unwrap();
+        assert!(matches!(e, TokenError::Reqwest(ref e) if e.is_timeout()));
+        assert_eq!(mock.token_requests(), 1);
+
+        mock.stop().await;
+    }
+}
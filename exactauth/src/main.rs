@@ -1,3 +1,4 @@
+use std::time::Duration;
 use actix_cors::Cors;
 use actix_web::{App, HttpServer, ResponseError, web};
 use actix_web::body::MessageBody;
@@ -9,20 +10,34 @@ use tracing_subscriber::EnvFilter;
 use tracing_subscriber::fmt::layer;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use dal::Mysql;
+use dal::{Database, LeaseHolder, OAuth2Token, TokenCipher};
+use crate::allowed_callers::AllowedCallers;
 use crate::config::Config;
+use crate::exact_api::{ExactClient, RetryPolicy};
+use crate::refresher::Refresher;
 use crate::routable::Routable;
+use crate::trusted_proxies::TrustedProxies;
 
+mod allowed_callers;
+mod audit;
 mod config;
 mod routes;
 mod exact_api;
 mod error;
 mod tasks;
+mod refresher;
 mod routable;
+mod trusted_proxies;
+#[cfg(test)]
+pub(crate) mod test_util;
 
-pub type MysqlData = web::Data<Mysql>;
+pub type DatabaseData = web::Data<Database>;
 pub type ConfigData = web::Data<Config>;
 pub type AuthData = web::Data<MrAuthClient>;
+pub type RefresherData = web::Data<Refresher>;
+pub type AllowedCallersData = web::Data<AllowedCallers>;
+pub type ExactClientData = web::Data<ExactClient>;
+pub type TrustedProxiesData = web::Data<TrustedProxies>;
 
 #[cfg(not(debug_assertions))]
 const BIND_PORT: u16 = 8080;
@@ -36,13 +51,44 @@ async fn main() -> std::io::Result<()> {
     info!("Starting server");
     debug!("Reading config");
     let config: Config = envy::from_env().expect("Reading config");
-    let mysql = Mysql::new(&config.mysql_user, &config.mysql_password, &config.mysql_host, &config.mysql_db).expect("Setting up DB");
+    let allowed_callers = web::Data::new(config.allowed_callers.parse::<AllowedCallers>().expect("Parsing allowed callers"));
+    let trusted_proxies = web::Data::new(config.trusted_proxies.parse::<TrustedProxies>().expect("Parsing trusted proxies"));
+    let token_cipher = TokenCipher::from_key_list(&config.token_encryption_key_id, &config.token_encryption_keys).expect("Setting up token encryption");
+    let database_config = config.database_config().expect("Reading database config");
+    let db = Database::new(database_config, token_cipher).await.expect("Setting up DB");
 
+    let reencrypted = OAuth2Token::reencrypt_all(db.clone()).await.expect("Re-encrypting tokens");
+    if reencrypted > 0 {
+        info!("Re-encrypted {reencrypted} tokens with key '{}'", config.token_encryption_key_id);
+    }
+
+    let exact_client = ExactClient::new(
+        config.exact_endpoints(),
+        Duration::from_secs(config.exact_connect_timeout_sec),
+        Duration::from_secs(config.exact_timeout_sec),
+        Duration::from_secs(config.exact_idle_timeout_sec),
+        RetryPolicy {
+            max_retries: config.exact_max_retries,
+            base_delay: Duration::from_millis(config.exact_retry_base_delay_millis),
+            max_delay: Duration::from_millis(config.exact_retry_max_delay_millis),
+        },
+    ).expect("Setting up Exact client");
+
+    let refresher = Refresher::new(
+        db.clone(),
+        LeaseHolder::new(),
+        exact_client.clone(),
+        config.exact_credentials(),
+    );
     tasks::refresh_tokens::start_refresh_token_task(
-        mysql.clone(),
-        &config.exact_client_id,
-        &config.exact_client_secret,
-        &config.redirect_uri
+        db.clone(),
+        refresher.clone(),
+        config.refresh_parallelism,
+    );
+    tasks::cleanup::start_cleanup_task(
+        db.clone(),
+        config.authorization_start_ttl_sec,
+        config.login_ticket_ttl_sec,
     );
 
     let authclient = MrAuthClient::new(
@@ -53,9 +99,13 @@ async fn main() -> std::io::Result<()> {
     HttpServer::new(move || App::new()
         .wrap(Cors::permissive())
         .wrap(tracing_actix_web::TracingLogger::<NoiselessRootSpanBuilder>::new())
-        .app_data(web::Data::new(mysql.clone()))
+        .app_data(web::Data::new(db.clone()))
         .app_data(web::Data::new(config.clone()))
         .app_data(web::Data::new(authclient.clone()))
+        .app_data(web::Data::new(refresher.clone()))
+        .app_data(web::Data::new(exact_client.clone()))
+        .app_data(allowed_callers.clone())
+        .app_data(trusted_proxies.clone())
         .configure(routes::Router::configure)
     ).bind(&format!("0.0.0.0:{BIND_PORT}"))?.run().await
 
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use actix_web::cookie::time;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use thiserror::Error;
+use tracing::{trace, warn};
+use dal::{AUDIT_ACTOR_SYSTEM, AuditAction, AuditOutcome, Connection, Database, LeaseHolder, NewAuditEvent, OAuth2Token};
+use crate::audit;
+use crate::exact_api::{ClientCredentials, ExactClient, TokenError};
+
+/// Tokens are refreshed when they are within this many seconds of expiring.
+/// Exact tokens are valid for 10 minutes, but may only be refreshed after
+/// they expire within 30 seconds. The 1 second difference is to provide a buffer
+pub const REFRESH_WINDOW_SEC: i64 = 29;
+/// How long a refresh lease is held for at most.
+/// Should be well above the time a token exchange with Exact takes
+const REFRESH_LEASE_SEC: i64 = 60;
+/// The time a refresh may spend on requests to Exact, including retries.
+/// Stays below [REFRESH_LEASE_SEC], so that the lease can not expire and be taken by another instance
+/// with the same refresh token while a request is in flight, and leaves time to store the new tokens
+const REFRESH_DEADLINE_SEC: u64 = 45;
+/// How often to check for the result when another instance holds the refresh lease
+const LEASE_POLL_INTERVAL_MILLIS: u64 = 250;
+
+#[derive(Debug, Error)]
+pub enum RefreshError {
+    #[error("DAL error: {0}")]
+    Dal(#[from] dal::Error),
+    #[error("Token error: {0}")]
+    Token(#[from] TokenError),
+    #[error("The Exact grant is no longer valid, the user must log in again")]
+    ReauthorizationRequired,
+    #[error("Timed out waiting for the tokens to be refreshed")]
+    Timeout,
+    #[error("The refresh task failed: {0}")]
+    Task(String),
+}
+
+/// The result of attempting to refresh the tokens of a single connection
+#[derive(Debug, Clone)]
+pub enum RefreshOutcome {
+    /// The connection has no tokens to refresh
+    NoTokens,
+    /// The access token is still valid for long enough
+    NotDue,
+    /// The tokens were refreshed
+    Refreshed,
+    /// Another instance is refreshing the tokens
+    Leased,
+    /// The Exact grant is no longer valid, the user must log in again
+    ReauthorizationRequired,
+}
+
+type InFlightRefresh = Shared<BoxFuture<'static, Result<RefreshOutcome, Arc<RefreshError>>>>;
+
+/// Refreshes the tokens of connections.
+/// Within this instance concurrent refreshes of the same connection share a single request to Exact,
+/// across instances the connection's refresh lease makes sure only one instance refreshes at a time.
+#[derive(Clone)]
+pub struct Refresher {
+    inner: Arc<RefresherInner>,
+}
+
+struct RefresherInner {
+    db: Database,
+    lease_holder: LeaseHolder,
+    exact_client: ExactClient,
+    credentials: ClientCredentials,
+    in_flight: Mutex<HashMap<String, InFlightRefresh>>,
+}
+
+impl Refresher {
+    pub fn new(db: Database, lease_holder: LeaseHolder, exact_client: ExactClient, credentials: ClientCredentials) -> Self {
+        Self {
+            inner: Arc::new(RefresherInner {
+                db,
+                lease_holder,
+                exact_client,
+                credentials,
+                in_flight: Mutex::new(HashMap::new()),
+            })
+        }
+    }
+
+    /// Refresh the tokens of the connection, if they are due.
+    /// If a refresh for the connection is already in flight, its result is shared instead.
+    ///
+    /// The refresh runs as a separate task, so that it finishes even if every caller waiting for it is dropped.
+    /// Otherwise Exact could rotate the refresh token without the new token ever being stored
+    pub async fn refresh(&self, connection: &Connection) -> Result<RefreshOutcome, Arc<RefreshError>> {
+        let in_flight = self.inner.in_flight.lock().unwrap()
+            .entry(connection.id.clone())
+            .or_insert_with(|| {
+                let inner = self.inner.clone();
+                let connection = connection.clone();
+
+                let task = tokio::spawn(async move {
+                    let _guard = InFlightGuard {
+                        inner: inner.clone(),
+                        connection_id: connection.id.clone(),
+                    };
+                    let outcome = inner.refresh_leased(connection.clone()).await;
+                    inner.record_outcome(&connection, &outcome).await;
+                    outcome.map_err(Arc::new)
+                });
+
+                async move {
+                    task.await.unwrap_or_else(|e| Err(Arc::new(RefreshError::Task(e.to_string()))))
+                }.boxed().shared()
+            })
+            .clone();
+
+        in_flight.await
+    }
+
+    /// Get the connection's access token, refreshing it first if it is expired or about to expire.
+    /// If another instance is refreshing the tokens, this waits for its result.
+    /// Returns `None` if the connection has no access token.
+    pub async fn get_valid_access_token(&self, connection: &Connection) -> Result<Option<OAuth2Token>, Arc<RefreshError>> {
+        let deadline = time::OffsetDateTime::now_utc().unix_timestamp() + REFRESH_LEASE_SEC;
+        let mut refreshed = false;
+
+        loop {
+            let access_token = match connection.get_access_token().await.map_err(|e| Arc::new(e.into()))? {
+                Some(x) => x,
+                None => return Ok(None),
+            };
+
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+            // A token that was just refreshed is accepted as long as it is valid,
+            // refreshing it again would not yield a token that is valid for longer
+            if !is_due(access_token.expiry, now) || (refreshed && access_token.expiry > now) {
+                return Ok(Some(access_token));
+            }
+
+            if connection.reauthorization_required {
+                return Err(Arc::new(RefreshError::ReauthorizationRequired));
+            }
+
+            if now > deadline {
+                return Err(Arc::new(RefreshError::Timeout));
+            }
+
+            match self.refresh(connection).await? {
+                RefreshOutcome::NoTokens => return Ok(None),
+                RefreshOutcome::ReauthorizationRequired => return Err(Arc::new(RefreshError::ReauthorizationRequired)),
+                RefreshOutcome::Leased => {
+                    trace!("Tokens of connection {} are being refreshed by another instance, waiting", connection.id);
+                    tokio::time::sleep(Duration::from_millis(LEASE_POLL_INTERVAL_MILLIS)).await;
+                },
+                RefreshOutcome::Refreshed | RefreshOutcome::NotDue => refreshed = true,
+            }
+        }
+    }
+}
+
+/// Removes the in-flight refresh of a connection once it finished, also if it panicked
+struct InFlightGuard {
+    inner: Arc<RefresherInner>,
+    connection_id: String,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        // The lock is never held across a panic, but do not panic while unwinding if it were
+        if let Ok(mut in_flight) = self.inner.in_flight.lock() {
+            in_flight.remove(&self.connection_id);
+        }
+    }
+}
+
+impl RefresherInner {
+    /// Record refreshes that touched the connection's tokens or grant in the audit log,
+    /// whether they were started by the refresh task or on demand
+    async fn record_outcome(&self, connection: &Connection, outcome: &Result<RefreshOutcome, RefreshError>) {
+        let event = NewAuditEvent {
+            actor: AUDIT_ACTOR_SYSTEM.to_string(),
+            user_id: Some(connection.user_id.clone()),
+            connection_id: Some(connection.id.clone()),
+            grantee_id: None,
+            action: AuditAction::TokensRefreshed,
+            outcome: AuditOutcome::Success,
+            detail: None,
+            ip: None,
+            user_agent: None,
+        };
+
+        match outcome {
+            Ok(RefreshOutcome::Refreshed) => audit::record(&self.db, event).await,
+            Ok(RefreshOutcome::ReauthorizationRequired) => audit::record_failure(&self.db, event, "Reauthorization required").await,
+            Err(e) => audit::record_failure(&self.db, event, e).await,
+            // Nothing touched the tokens
+            Ok(RefreshOutcome::NoTokens | RefreshOutcome::NotDue | RefreshOutcome::Leased) => {}
+        }
+    }
+
+    /// Refresh the tokens of the connection while holding the connection's refresh lease
+    async fn refresh_leased(&self, mut connection: Connection) -> Result<RefreshOutcome, RefreshError> {
+        let lease = match connection.try_acquire_refresh_lease(&self.lease_holder, REFRESH_LEASE_SEC).await? {
+            Some(x) => x,
+            None => return Ok(RefreshOutcome::Leased),
+        };
+
+        let deadline = Instant::now() + Duration::from_secs(REFRESH_DEADLINE_SEC);
+        let outcome = self.refresh_connection(&mut connection, deadline).await;
+        if let Err(e) = lease.release().await {
+            warn!("Failed to release refresh lease for connection {}: {e}", connection.id);
+        }
+
+        match outcome {
+            Ok(RefreshOutcome::Refreshed) if connection.refresh_failures > 0 => {
+                connection.reset_refresh_state().await?;
+                Ok(RefreshOutcome::Refreshed)
+            },
+            Err(RefreshError::Token(e)) if e.is_invalid_grant() => {
+                warn!("Exact grant of connection {} is no longer valid, reauthorization is required", connection.id);
+                connection.set_reauthorization_required().await?;
+                Ok(RefreshOutcome::ReauthorizationRequired)
+            },
+            outcome => outcome,
+        }
+    }
+
+    async fn refresh_connection(&self, connection: &mut Connection, deadline: Instant) -> Result<RefreshOutcome, RefreshError> {
+        let access_token = match connection.get_access_token().await? {
+            Some(x) => x,
+            None => return Ok(RefreshOutcome::NoTokens),
+        };
+
+        let refresh_token = match connection.get_refresh_token().await? {
+            Some(x) => x,
+            None => return Ok(RefreshOutcome::NoTokens),
+        };
+
+        // The token may have been refreshed since it was found to be due,
+        // e.g. by another instance
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        trace!("Access token for connection {} expires at {}", connection.id, access_token.expiry);
+        if !is_due(access_token.expiry, now) {
+            trace!("Access token for connection {} is not yet expired", connection.id);
+            return Ok(RefreshOutcome::NotDue);
+        }
+
+        trace!("Access token for connection {} has expired, or must be refreshed", connection.id);
+
+        // Refresh the token
+        let refreshed_pair = crate::exact_api::refresh_tokens(
+            &self.exact_client,
+            &self.credentials,
+            deadline,
+            connection.region,
+            &refresh_token.token
+        ).await?;
+
+        // Exact rotates the refresh token, the new pair must replace the old pair as a whole
+        connection.set_token_pair(
+            &refreshed_pair.access,
+            refreshed_pair.access_expiry,
+            &refreshed_pair.refresh,
+            refreshed_pair.refresh_expiry
+        ).await?;
+
+        // The new tokens are stored, failing to record when is not a failed refresh
+        let refreshed_at = time::OffsetDateTime::now_utc().unix_timestamp();
+        if let Err(e) = connection.set_last_refreshed_at(refreshed_at).await {
+            warn!("Failed to record refresh time for connection {}: {e}", connection.id);
+        }
+
+        trace!("Refreshed tokens for connection {}", connection.id);
+        Ok(RefreshOutcome::Refreshed)
+    }
+}
+
+/// Whether a token expiring at `expiry` should be refreshed at `now`
+fn is_due(expiry: i64, now: i64) -> bool {
+    expiry - now < REFRESH_WINDOW_SEC
+}
@@ -1,22 +1,63 @@
 use actix_multiresponse::Payload;
+use actix_web::web;
 use mrauth::actix::BearerHeader;
-use dal::User;
-use crate::{AuthData, MysqlData};
+use serde::Deserialize;
+use dal::AuditAction;
+use crate::{AuthData, DatabaseData, RefresherData};
+use crate::audit::{self, ClientInfo};
 use crate::error::{Error, WebResult};
+use crate::routes::v1::connections::select_connection;
 use proto::GetAccessTokenResponse;
 
 pub const SCOPE: &str = "nl.mrfriendly.exact";
 
-pub async fn access_token(mysql: MysqlData, auth: AuthData, bearer: BearerHeader) -> WebResult<Payload<GetAccessTokenResponse>> {
+#[derive(Deserialize)]
+pub struct Query {
+    /// The ID of the connection. May be omitted if the user has a single connection
+    connection: Option<String>,
+    /// Space separated Exact scopes the caller requires the token to have
+    scopes: Option<String>,
+}
+
+pub async fn access_token(db: DatabaseData, auth: AuthData, refresher: RefresherData, bearer: BearerHeader, client: ClientInfo, query: web::Query<Query>) -> WebResult<Payload<GetAccessTokenResponse>> {
     let auth_user = mrauth::User::get_user(&auth, &bearer, SCOPE).await?;
-    let user = User::get_by_id(mysql.as_ref().clone(), &auth_user.id)?
-        .ok_or(Error::NotFound)?;
+    // Shared connections are only selected if the user was granted access to them
+    let connection = select_connection(&db, &auth_user.id, query.connection.as_deref()).await?;
+
+    let event = client.audit_event(&auth_user.id, Some(&connection.user_id), Some(&connection.id), AuditAction::AccessTokenFetched);
+
+    // A token without the required scopes would only fail at Exact
+    let required_scopes = query.scopes.as_deref()
+        .map(|scopes| scopes.split_whitespace().collect::<Vec<_>>())
+        .unwrap_or_default();
+    let missing_scopes = connection.missing_scopes(&required_scopes);
+    if !missing_scopes.is_empty() {
+        let missing_scopes: Vec<String> = missing_scopes.into_iter().map(str::to_string).collect();
+        audit::record_failure(&db, event, format!("Missing scopes: {}", missing_scopes.join(" "))).await;
+        return Err(Error::InsufficientScopes(missing_scopes));
+    }
+
+    // Never hand out an expired token, even if the refresh task is behind
+    let access_token = match refresher.get_valid_access_token(&connection).await {
+        Ok(Some(x)) => x,
+        Ok(None) => {
+            audit::record_failure(&db, event, "No access token").await;
+            return Err(Error::NotFound);
+        },
+        Err(e) => {
+            audit::record_failure(&db, event, &e).await;
+            return Err(e.into());
+        }
+    };
 
-    let access_token = user.get_access_token()?
-        .ok_or(Error::NotFound)?;
+    audit::record(&db, event).await;
 
     Ok(Payload(GetAccessTokenResponse {
         token: access_token.token,
-        expires_at: access_token.expiry
+        expires_at: access_token.expiry,
+        scopes: connection.requested_scopes()
+            .map(|scopes| scopes.into_iter().map(str::to_string).collect())
+            .unwrap_or_default(),
+        connection_id: connection.id,
     }))
-}
\ No newline at end of file
+}
@@ -0,0 +1,79 @@
+use actix_multiresponse::Payload;
+use actix_web::web;
+use mrauth::actix::BearerHeader;
+use serde::Deserialize;
+use dal::{AuditAction, AuditEvent, AuditEventFilter, AuditOutcome};
+use crate::{AuthData, DatabaseData};
+use crate::error::{Error, WebResult};
+use crate::routes::v1::admin::ADMIN_SCOPE;
+use proto::ListAuditEventsResponse;
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 500;
+
+#[derive(Deserialize)]
+pub struct Query {
+    /// Only events performed by this MrAuth user, or by `system`
+    actor: Option<String>,
+    /// Only events concerning the tokens of this MrAuth user
+    user: Option<String>,
+    action: Option<String>,
+    outcome: Option<String>,
+    /// Only events recorded at or after this UNIX timestamp
+    from: Option<i64>,
+    /// Only events recorded at or before this UNIX timestamp
+    to: Option<i64>,
+    /// The `nextBefore` of the previous page
+    before: Option<i64>,
+    limit: Option<u32>,
+}
+
+pub async fn audit_events(db: DatabaseData, auth: AuthData, bearer: BearerHeader, query: web::Query<Query>) -> WebResult<Payload<ListAuditEventsResponse>> {
+    mrauth::User::get_user(&auth, &bearer, ADMIN_SCOPE).await?;
+
+    let query = query.into_inner();
+    let filter = AuditEventFilter {
+        actor: query.actor,
+        user_id: query.user,
+        action: query.action
+            .map(|action| action.parse::<AuditAction>())
+            .transpose()
+            .map_err(|e| Error::BadRequest(e.to_string()))?,
+        outcome: query.outcome
+            .map(|outcome| outcome.parse::<AuditOutcome>())
+            .transpose()
+            .map_err(|e| Error::BadRequest(e.to_string()))?,
+        from: query.from,
+        to: query.to,
+        before_id: query.before,
+    };
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let events = AuditEvent::list(db.as_ref().clone(), &filter, limit).await?;
+
+    // A full page means there may be more events
+    let next_before = if events.len() == limit as usize {
+        events.last().map(|event| event.id)
+    } else {
+        None
+    };
+
+    Ok(Payload(ListAuditEventsResponse {
+        events: events.into_iter()
+            .map(|event| proto::AuditEvent {
+                id: event.id,
+                timestamp: event.timestamp,
+                actor: event.actor,
+                user_id: event.user_id,
+                connection_id: event.connection_id,
+                grantee_id: event.grantee_id,
+                action: event.action.to_string(),
+                outcome: event.outcome.to_string(),
+                detail: event.detail,
+                ip: event.ip,
+                user_agent: event.user_agent,
+            })
+            .collect(),
+        next_before,
+    }))
+}
@@ -0,0 +1,75 @@
+use actix_multiresponse::Payload;
+use actix_web::{HttpResponse, web};
+use mrauth::actix::BearerHeader;
+use dal::{AuditAction, Connection, ConnectionGrant, NewAuditEvent};
+use crate::{AuthData, DatabaseData};
+use crate::audit::{self, ClientInfo};
+use crate::error::{Error, WebResult};
+use crate::routes::v1::admin::ADMIN_SCOPE;
+use proto::{CreateConnectionGrantRequest, ListConnectionGrantsResponse};
+
+pub async fn list_grants(db: DatabaseData, auth: AuthData, bearer: BearerHeader, connection_id: web::Path<String>) -> WebResult<Payload<ListConnectionGrantsResponse>> {
+    mrauth::User::get_user(&auth, &bearer, ADMIN_SCOPE).await?;
+
+    let connection = Connection::get_by_id(db.as_ref().clone(), &connection_id).await?
+        .ok_or(Error::NotFound)?;
+
+    Ok(Payload(ListConnectionGrantsResponse {
+        grants: connection.list_grants().await?
+            .into_iter()
+            .map(grant_to_proto)
+            .collect(),
+    }))
+}
+
+/// Allow a MrAuth user or service account to use a shared connection
+pub async fn create_grant(db: DatabaseData, auth: AuthData, bearer: BearerHeader, client: ClientInfo, connection_id: web::Path<String>, Payload(request): Payload<CreateConnectionGrantRequest>) -> WebResult<Payload<proto::ConnectionGrant>> {
+    let admin = mrauth::User::get_user(&auth, &bearer, ADMIN_SCOPE).await?;
+
+    if request.grantee_id.is_empty() {
+        return Err(Error::BadRequest("A grantee is required".into()));
+    }
+
+    let connection = Connection::get_by_id(db.as_ref().clone(), &connection_id).await?
+        .ok_or(Error::NotFound)?;
+    if connection.owner_id.is_none() {
+        return Err(Error::BadRequest("Only shared connections can be granted".into()));
+    }
+
+    let grant = connection.grant(&request.grantee_id, &admin.id).await?
+        .ok_or(Error::BadRequest("The grantee already has access".into()))?;
+
+    audit::record(&db, NewAuditEvent {
+        grantee_id: Some(grant.grantee_id.clone()),
+        ..client.audit_event(&admin.id, Some(&connection.user_id), Some(&connection.id), AuditAction::AccessGranted)
+    }).await;
+
+    Ok(Payload(grant_to_proto(grant)))
+}
+
+pub async fn revoke_grant(db: DatabaseData, auth: AuthData, bearer: BearerHeader, client: ClientInfo, path: web::Path<(String, String)>) -> WebResult<HttpResponse> {
+    let admin = mrauth::User::get_user(&auth, &bearer, ADMIN_SCOPE).await?;
+    let (connection_id, grantee_id) = path.into_inner();
+
+    let connection = Connection::get_by_id(db.as_ref().clone(), &connection_id).await?
+        .ok_or(Error::NotFound)?;
+    if !connection.revoke(&grantee_id).await? {
+        return Err(Error::NotFound);
+    }
+
+    audit::record(&db, NewAuditEvent {
+        grantee_id: Some(grantee_id),
+        ..client.audit_event(&admin.id, Some(&connection.user_id), Some(&connection.id), AuditAction::AccessRevoked)
+    }).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+fn grant_to_proto(grant: ConnectionGrant) -> proto::ConnectionGrant {
+    proto::ConnectionGrant {
+        connection_id: grant.connection_id,
+        grantee_id: grant.grantee_id,
+        granted_by: grant.granted_by,
+        granted_at: grant.granted_at,
+    }
+}
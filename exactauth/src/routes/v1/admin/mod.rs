@@ -0,0 +1,29 @@
+use actix_web::web;
+use actix_web::web::ServiceConfig;
+use crate::routable::Routable;
+
+mod audit_events;
+mod grants;
+mod owners;
+mod users;
+
+/// MrAuth scope required for all admin endpoints
+pub const ADMIN_SCOPE: &str = "nl.mrfriendly.exact.admin";
+
+pub struct Router;
+
+impl Routable for Router {
+    fn configure(config: &mut ServiceConfig) {
+        config.service(web::scope("/admin")
+            .route("/audit-events", web::get().to(audit_events::audit_events))
+            .route("/owners", web::get().to(owners::list_owners))
+            .route("/owners", web::post().to(owners::create_owner))
+            .route("/owners/{owner_id}/connections", web::get().to(owners::owner_connections))
+            .route("/connections/{connection_id}/owner", web::put().to(owners::set_connection_owner))
+            .route("/connections/{connection_id}/grants", web::get().to(grants::list_grants))
+            .route("/connections/{connection_id}/grants", web::post().to(grants::create_grant))
+            .route("/connections/{connection_id}/grants/{grantee_id}", web::delete().to(grants::revoke_grant))
+            .route("/users/{user_id}", web::delete().to(users::purge_user))
+        );
+    }
+}
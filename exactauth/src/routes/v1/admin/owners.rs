@@ -0,0 +1,103 @@
+use actix_multiresponse::Payload;
+use actix_web::web;
+use mrauth::actix::BearerHeader;
+use dal::{AuditAction, Connection, MAX_OWNER_NAME_LEN, NewAuditEvent, Owner, OwnerKind, User};
+use crate::{AuthData, DatabaseData};
+use crate::audit::{self, ClientInfo};
+use crate::error::{Error, WebResult};
+use crate::routes::v1::admin::ADMIN_SCOPE;
+use crate::routes::v1::connections::connection_to_proto;
+use proto::{CreateOwnerRequest, ListConnectionsResponse, ListOwnersResponse, SetConnectionOwnerRequest};
+
+pub async fn list_owners(db: DatabaseData, auth: AuthData, bearer: BearerHeader) -> WebResult<Payload<ListOwnersResponse>> {
+    mrauth::User::get_user(&auth, &bearer, ADMIN_SCOPE).await?;
+
+    let owners = Owner::list_all(db.as_ref().clone()).await?;
+    Ok(Payload(ListOwnersResponse {
+        owners: owners.into_iter()
+            .map(owner_to_proto)
+            .collect(),
+    }))
+}
+
+pub async fn create_owner(db: DatabaseData, auth: AuthData, bearer: BearerHeader, client: ClientInfo, Payload(request): Payload<CreateOwnerRequest>) -> WebResult<Payload<proto::Owner>> {
+    let admin = mrauth::User::get_user(&auth, &bearer, ADMIN_SCOPE).await?;
+
+    if request.name.is_empty() || request.name.chars().count() > MAX_OWNER_NAME_LEN {
+        return Err(Error::BadRequest(format!("Owner name must be between 1 and {MAX_OWNER_NAME_LEN} characters")));
+    }
+
+    let kind = request.kind.parse::<OwnerKind>()
+        .map_err(|e| Error::BadRequest(e.to_string()))?;
+
+    let owner = Owner::create(db.as_ref().clone(), &request.name, kind).await?;
+
+    audit::record(&db, NewAuditEvent {
+        detail: Some(format!("Created {} owner {} ({})", owner.kind, owner.id, owner.name)),
+        ..client.audit_event(&admin.id, None, None, AuditAction::OwnerCreated)
+    }).await;
+
+    Ok(Payload(owner_to_proto(owner)))
+}
+
+/// The connections shared by the owner
+pub async fn owner_connections(db: DatabaseData, auth: AuthData, bearer: BearerHeader, owner_id: web::Path<String>) -> WebResult<Payload<ListConnectionsResponse>> {
+    mrauth::User::get_user(&auth, &bearer, ADMIN_SCOPE).await?;
+
+    let owner = Owner::get_by_id(db.as_ref().clone(), &owner_id).await?
+        .ok_or(Error::NotFound)?;
+
+    Ok(Payload(ListConnectionsResponse {
+        connections: owner.list_connections().await?
+            .into_iter()
+            .map(connection_to_proto)
+            .collect(),
+    }))
+}
+
+/// Share a connection by transferring it to an owner, or make it personal again.
+/// While shared, the user who logged in to Exact for the connection reauthorizes it with a login ticket for its ID
+pub async fn set_connection_owner(db: DatabaseData, auth: AuthData, bearer: BearerHeader, client: ClientInfo, connection_id: web::Path<String>, Payload(request): Payload<SetConnectionOwnerRequest>) -> WebResult<Payload<proto::Connection>> {
+    let admin = mrauth::User::get_user(&auth, &bearer, ADMIN_SCOPE).await?;
+
+    let mut connection = Connection::get_by_id(db.as_ref().clone(), &connection_id).await?
+        .ok_or(Error::NotFound)?;
+
+    if let Some(owner_id) = &request.owner_id {
+        Owner::get_by_id(db.as_ref().clone(), owner_id).await?
+            .ok_or(Error::BadRequest("Unknown owner".into()))?;
+    } else if connection.owner_id.is_some() {
+        // A user has at most one personal connection per label
+        let user = User::get_by_id(db.as_ref().clone(), &connection.user_id).await?
+            .ok_or(Error::NotFound)?;
+        if user.get_connection_by_label(connection.label.as_deref()).await?.is_some() {
+            return Err(Error::Conflict("The user already has a personal connection with this label".into()));
+        }
+    }
+
+    let previous_owner = connection.owner_id.clone();
+    connection.set_owner(request.owner_id.as_deref()).await?;
+
+    let detail = match (&previous_owner, &connection.owner_id) {
+        (Some(previous), Some(owner)) if previous == owner => format!("Kept shared with owner {owner}"),
+        (Some(previous), Some(owner)) => format!("Transferred from owner {previous} to owner {owner}"),
+        (None, Some(owner)) => format!("Shared with owner {owner}"),
+        (Some(previous), None) => format!("Made personal, was shared with owner {previous}"),
+        (None, None) => "Kept personal".to_string(),
+    };
+    audit::record(&db, NewAuditEvent {
+        detail: Some(detail),
+        ..client.audit_event(&admin.id, Some(&connection.user_id), Some(&connection.id), AuditAction::ConnectionOwnerChanged)
+    }).await;
+
+    Ok(Payload(connection_to_proto(connection)))
+}
+
+fn owner_to_proto(owner: Owner) -> proto::Owner {
+    proto::Owner {
+        id: owner.id,
+        name: owner.name,
+        kind: owner.kind.to_string(),
+        created_at: owner.created_at,
+    }
+}
@@ -0,0 +1,40 @@
+use actix_web::{HttpResponse, web};
+use mrauth::actix::BearerHeader;
+use dal::{AuditAction, Connection, User};
+use crate::{AuthData, DatabaseData};
+use crate::audit::{self, ClientInfo};
+use crate::error::{Error, WebResult};
+use crate::routes::v1::admin::ADMIN_SCOPE;
+
+/// Delete a user with everything stored about it, e.g. to honour an offboarding or data-deletion request.
+/// Shared connections are not deleted along with the user who logged in to them,
+/// the request fails with `409 Conflict` listing them until they are made personal or deleted.
+/// The user's audit events are kept, without IP address and user agent, and with the user's ID replaced by a pseudonym
+pub async fn purge_user(db: DatabaseData, auth: AuthData, bearer: BearerHeader, client: ClientInfo, user_id: web::Path<String>) -> WebResult<HttpResponse> {
+    let admin = mrauth::User::get_user(&auth, &bearer, ADMIN_SCOPE).await?;
+
+    let event = client.audit_event(&admin.id, Some(&user_id), None, AuditAction::UserPurged);
+    let shared_connections = Connection::list_shared_of_user(db.as_ref().clone(), &user_id).await?;
+    if !shared_connections.is_empty() {
+        let ids = shared_connections.iter()
+            .map(|connection| connection.id.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        audit::record_failure(&db, event, format!("User logged in to shared connections {ids}")).await;
+        return Err(Error::Conflict(format!("The user logged in to shared connections, make them personal or delete them first: {ids}")));
+    }
+
+    let pseudonym = match User::purge(db.as_ref().clone(), &user_id).await {
+        Ok(Some(x)) => x,
+        Ok(None) => return Err(Error::NotFound),
+        Err(e) => {
+            audit::record_failure(&db, event, &e).await;
+            return Err(e.into());
+        }
+    };
+
+    // The purge itself must not link the pseudonym back to the user either
+    let event = client.audit_event(&admin.id, Some(&pseudonym), None, AuditAction::UserPurged);
+    audit::record(&db, event).await;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,97 @@
+use actix_multiresponse::Payload;
+use actix_web::{HttpResponse, web};
+use mrauth::actix::BearerHeader;
+use serde::Deserialize;
+use dal::{AuditAction, Connection, Database, User};
+use crate::{AuthData, DatabaseData};
+use crate::audit::{self, ClientInfo};
+use crate::error::{Error, WebResult};
+use proto::ListConnectionsResponse;
+
+const SCOPE: &str = "nl.mrfriendly.exact";
+
+/// Selects one of the user's connections
+#[derive(Deserialize)]
+pub struct ConnectionQuery {
+    /// The ID of the connection. May be omitted if the user has a single connection
+    pub connection: Option<String>,
+}
+
+pub async fn connections(db: DatabaseData, auth: AuthData, bearer: BearerHeader) -> WebResult<Payload<ListConnectionsResponse>> {
+    let auth_user = mrauth::User::get_user(&auth, &bearer, SCOPE).await?;
+    let mut connections = match User::get_by_id(db.as_ref().clone(), &auth_user.id).await? {
+        Some(user) => user.list_connections().await?,
+        None => Vec::new(),
+    };
+    connections.extend(Connection::list_granted(db.as_ref().clone(), &auth_user.id).await?);
+
+    Ok(Payload(ListConnectionsResponse {
+        connections: connections.into_iter()
+            .map(connection_to_proto)
+            .collect(),
+    }))
+}
+
+/// Delete one of the user's own connections with its tokens, and any logins the user did not complete.
+/// Shared connections can not be deleted by their grantees
+pub async fn delete_connection(db: DatabaseData, auth: AuthData, bearer: BearerHeader, client: ClientInfo, query: web::Query<ConnectionQuery>) -> WebResult<HttpResponse> {
+    let auth_user = mrauth::User::get_user(&auth, &bearer, SCOPE).await?;
+    let user = User::get_by_id(db.as_ref().clone(), &auth_user.id).await?
+        .ok_or(Error::NotFound)?;
+    let connection = select_own_connection(&user, query.connection.as_deref()).await?;
+
+    let event = client.audit_event(&auth_user.id, Some(&user.id), Some(&connection.id), AuditAction::ConnectionDeleted);
+    if let Err(e) = connection.delete().await {
+        audit::record_failure(&db, event, &e).await;
+        return Err(e.into());
+    }
+
+    audit::record(&db, event).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn connection_to_proto(connection: Connection) -> proto::Connection {
+    proto::Connection {
+        id: connection.id,
+        label: connection.label,
+        region: connection.region.to_string(),
+        reauthorization_required: connection.reauthorization_required,
+        owner_id: connection.owner_id,
+    }
+}
+
+/// Select the connection with the provided ID, if the MrAuth user may use it.
+/// Shared connections must always be selected by ID. Without an ID the user's only own connection is selected,
+/// which is ambiguous if the user has several.
+pub async fn select_connection(db: &Database, user_id: &str, id: Option<&str>) -> WebResult<Connection> {
+    if let Some(id) = id {
+        let connection = Connection::get_by_id(db.clone(), id).await?
+            .ok_or(Error::NotFound)?;
+        if !connection.is_accessible_by(user_id).await? {
+            return Err(Error::NotFound);
+        }
+
+        return Ok(connection);
+    }
+
+    let user = User::get_by_id(db.clone(), user_id).await?
+        .ok_or(Error::NotFound)?;
+    select_own_connection(&user, None).await
+}
+
+/// Select the connection of the user with the provided ID, if it is not shared.
+/// Without an ID the user's only connection is selected, which is ambiguous if the user has several.
+pub async fn select_own_connection(user: &User, id: Option<&str>) -> WebResult<Connection> {
+    if let Some(id) = id {
+        return user.get_connection(id).await?
+            .ok_or(Error::NotFound);
+    }
+
+    let mut connections = user.list_connections().await?;
+    match connections.len() {
+        0 => Err(Error::NotFound),
+        1 => Ok(connections.remove(0)),
+        _ => Err(Error::BadRequest("The user has multiple connections, one must be selected".into())),
+    }
+}
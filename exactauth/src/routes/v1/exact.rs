@@ -0,0 +1,141 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use mrauth::actix::BearerHeader;
+use tracing::instrument;
+use dal::{AuditAction, NewAuditEvent};
+use crate::{AuthData, DatabaseData, ExactClientData, RefresherData};
+use crate::audit::{self, ClientInfo};
+use crate::error::{Error, WebResult};
+use crate::routes::v1::connections::select_connection;
+
+const SCOPE: &str = "nl.mrfriendly.exact";
+/// Selects the connection to forward the request for.
+/// May be omitted if the user has a single connection
+const CONNECTION_HEADER: &str = "x-exactauth-connection";
+/// The path prefix of the proxy route, the rest of the path is forwarded
+const PATH_PREFIX: &str = "/api/v1/exact/";
+
+/// Headers which only apply to a single connection, and thus should not be forwarded.
+/// `Authorization` and `Host` are replaced for the upstream request,
+/// `Content-Length` is recomputed for both the upstream request and the streamed response.
+const SKIPPED_HEADERS: [&str; 12] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "authorization",
+    "host",
+    "content-length",
+    CONNECTION_HEADER,
+];
+
+/// Forward a request to the Exact REST API of the connection's region.
+/// `/api/v1/exact/{tail}` is forwarded to `{region host}/api/{tail}`, with the stored Exact access token attached.
+#[instrument(skip_all)]
+pub async fn exact(db: DatabaseData, auth: AuthData, refresher: RefresherData, bearer: BearerHeader, req: HttpRequest, body: web::Bytes) -> WebResult<HttpResponse> {
+    // Taken from the request rather than extracted, to stay within a reasonable number of arguments
+    let exact_client = req.app_data::<ExactClientData>()
+        .expect("The Exact client is registered as app data")
+        .clone();
+    let auth_user = mrauth::User::get_user(&auth, &bearer, SCOPE).await?;
+
+    // The raw path rather than the decoded path parameter, which would turn an encoded '?' or '#' into a query or fragment
+    let tail = req.uri().path().strip_prefix(PATH_PREFIX)
+        .ok_or(Error::NotFound)?;
+    // The URL would be normalized, forwarding the request outside of `/api` with the user's token
+    if has_dot_segment(tail) {
+        return Err(Error::BadRequest("The path may not contain '.' or '..' segments".into()));
+    }
+
+    let connection_id = req.headers().get(CONNECTION_HEADER)
+        .map(|value| value.to_str())
+        .transpose()
+        .map_err(|_| Error::BadRequest("Invalid connection header".into()))?;
+    let connection = select_connection(&db, &auth_user.id, connection_id).await?;
+
+    // The query string is left out of the audit log, it may contain business data
+    let event = NewAuditEvent {
+        detail: Some(format!("{} /api/{tail}", req.method())),
+        ..ClientInfo::from_http_request(&req).audit_event(&auth_user.id, Some(&connection.user_id), Some(&connection.id), AuditAction::ExactRequestForwarded)
+    };
+
+    let access_token = match refresher.get_valid_access_token(&connection).await {
+        Ok(Some(x)) => x,
+        Ok(None) => {
+            audit::record_failure(&db, event, "No access token").await;
+            return Err(Error::NotFound);
+        },
+        Err(e) => {
+            audit::record_failure(&db, event, &e).await;
+            return Err(e.into());
+        }
+    };
+
+    let mut url = exact_client.endpoints().url(connection.region, &format!("/api/{tail}"));
+    if !req.query_string().is_empty() {
+        url = format!("{url}?{}", req.query_string());
+    }
+
+    let mut upstream_request = exact_client.http()
+        .request(req.method().clone(), url)
+        .bearer_auth(&access_token.token)
+        .body(body);
+    for (name, value) in req.headers() {
+        if !SKIPPED_HEADERS.contains(&name.as_str()) {
+            upstream_request = upstream_request.header(name.clone(), value.clone());
+        }
+    }
+
+    // Not retried, the request may not be idempotent
+    let upstream_response = match exact_client.send_streaming(upstream_request).await {
+        Ok(x) => x,
+        Err(e) => {
+            audit::record_failure(&db, event, &e).await;
+            return Err(e.into());
+        }
+    };
+    audit::record(&db, event).await;
+
+    let mut response = HttpResponse::build(upstream_response.status());
+    for (name, value) in upstream_response.headers() {
+        if !SKIPPED_HEADERS.contains(&name.as_str()) {
+            response.append_header((name.clone(), value.clone()));
+        }
+    }
+
+    Ok(response.streaming(exact_client.stream_body(upstream_response)))
+}
+
+/// Whether any segment of the path is `.` or `..`, possibly percent-encoded.
+/// Backslashes are treated as separators as well, as some servers do
+fn has_dot_segment(path: &str) -> bool {
+    path.split(['/', '\\'])
+        .map(|segment| segment.to_ascii_lowercase().replace("%2e", "."))
+        .any(|segment| segment == "." || segment == "..")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_segments() {
+        assert!(has_dot_segment(".."));
+        assert!(has_dot_segment("v1/../../oauth2/token"));
+        assert!(has_dot_segment("v1/%2e%2E/oauth2/token"));
+        assert!(has_dot_segment("v1/.%2e/oauth2/token"));
+        assert!(has_dot_segment("v1\\..\\oauth2/token"));
+        assert!(has_dot_segment("v1/./current/Me"));
+    }
+
+    #[test]
+    fn no_dot_segments() {
+        assert!(!has_dot_segment("v1/current/Me"));
+        assert!(!has_dot_segment("v1/123/salesinvoice/SalesInvoices"));
+        assert!(!has_dot_segment("v1/123/documents/file.pdf"));
+        assert!(!has_dot_segment("v1/123/..."));
+    }
+}
@@ -1,33 +1,139 @@
 use actix_web::web;
-use serde::Deserialize;
-use tracing::instrument;
-use dal::User;
-use crate::{ConfigData, MysqlData};
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+use dal::{AuditAction, Connection, User};
+use crate::{AllowedCallersData, ConfigData, DatabaseData, ExactClientData};
+use crate::audit::{self, ClientInfo};
 use crate::error::{Error, WebResult};
-use crate::exact_api::exchange_code_for_token;
+use crate::exact_api::{exchange_code_for_token, get_me};
 use crate::routes::redirect::Redirect;
 
+/// The query Exact redirects the user back with.
+/// On success `code` is set, on failure, e.g. when the user denies access, `error` is set instead.
 #[derive(Deserialize)]
 pub struct Query {
-    code: String,
+    code: Option<String>,
     state: String,
+    error: Option<String>,
+    error_description: Option<String>,
 }
 
-#[instrument(skip(mysql, config, query))]
-pub async fn logged_in(mysql: MysqlData, config: ConfigData, query: web::Query<Query>) -> WebResult<Redirect> {
-    let auth_start = User::get_by_authorization_start_id(mysql.as_ref().clone(), &query.state)?
+/// The query the caller is redirected back to if the authorization failed
+#[derive(Serialize)]
+struct ErrorQuery<'a> {
+    error: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_description: Option<&'a str>,
+}
+
+/// The query the caller is redirected back to if the authorization succeeded
+#[derive(Serialize)]
+struct SuccessQuery<'a> {
+    /// The ID of the connection the user logged in to
+    connection: &'a str,
+}
+
+/// Error code used when Exact redirects back with neither a code nor an error
+const ERROR_INVALID_REQUEST: &str = "invalid_request";
+
+#[instrument(skip(db, config, allowed_callers, exact_client, query, client))]
+pub async fn logged_in(db: DatabaseData, config: ConfigData, allowed_callers: AllowedCallersData, exact_client: ExactClientData, query: web::Query<Query>, client: ClientInfo) -> WebResult<Redirect> {
+    // The state is consumed right away, so that it can not be replayed
+    let auth_start = User::consume_authorization_start(db.as_ref().clone(), &query.state).await?
         .ok_or(Error::Forbidden("Unknown state".into()))?;
 
-    let token_pair = exchange_code_for_token(
-        &config.exact_client_id,
-        &config.exact_client_secret,
-        &config.redirect_uri,
-        &query.code,
-    ).await?;
+    let mut event = client.audit_event(&auth_start.user.id, Some(&auth_start.user.id), None, AuditAction::LoginCompleted);
+    if auth_start.is_expired(config.authorization_start_ttl_sec) {
+        audit::record_failure(&db, event, "Expired state").await;
+        return Err(Error::Forbidden("Expired state".into()));
+    }
+
+    // The list of allowed callers may have changed since the login was started
+    if !allowed_callers.is_allowed(&auth_start.caller) {
+        audit::record_failure(&db, event, format!("Caller '{}' is not allowed", auth_start.caller)).await;
+        return Err(Error::Forbidden("Caller is not allowed".into()));
+    }
+
+    let code = match (&query.code, &query.error) {
+        (Some(code), None) => code,
+        (_, error) => {
+            let error = error.as_deref().unwrap_or(ERROR_INVALID_REQUEST);
+            warn!("Exact authorization for user {} failed: {error}", auth_start.user.id);
+            audit::record_failure(&db, event, format!("Exact authorization failed: {error}")).await;
+
+            let error_query = serde_qs::to_string(&ErrorQuery {
+                error,
+                error_description: query.error_description.as_deref(),
+            }).unwrap();
+            return Ok(Redirect::new(append_query(&auth_start.caller, &error_query)));
+        }
+    };
 
     let user = auth_start.user;
-    user.set_access_token(&token_pair.access, token_pair.access_expiry)?;
-    user.set_refresh_token(&token_pair.refresh, token_pair.refresh_expiry)?;
 
-    Ok(Redirect::new(auth_start.caller))
-}
\ No newline at end of file
+    let token_pair = match exchange_code_for_token(
+        &exact_client,
+        &config.exact_credentials(),
+        auth_start.region,
+        code,
+    ).await {
+        Ok(x) => x,
+        Err(e) => {
+            audit::record_failure(&db, event, &e).await;
+            return Err(e.into());
+        }
+    };
+
+    // Logging in to an existing connection again replaces its region, scopes and tokens
+    let mut connection = match &auth_start.connection_id {
+        Some(connection_id) => {
+            // The connection may have been deleted or transferred while the user was logging in
+            let connection = Connection::get_by_id(db.as_ref().clone(), connection_id).await?
+                .filter(|connection| connection.user_id == user.id && connection.owner_id.is_some());
+            let mut connection = match connection {
+                Some(x) => x,
+                None => {
+                    audit::record_failure(&db, event, format!("Shared connection '{connection_id}' can no longer be reauthorized by the user")).await;
+                    return Err(Error::Forbidden("Connection can no longer be reauthorized".into()));
+                }
+            };
+
+            connection.set_region(auth_start.region).await?;
+            connection.set_scopes(&auth_start.exact_scopes).await?;
+            connection
+        },
+        None => user.create_connection(auth_start.connection_label.as_deref(), auth_start.region, &auth_start.exact_scopes).await?,
+    };
+
+    event.connection_id = Some(connection.id.clone());
+
+    connection.set_token_pair(&token_pair.access, token_pair.access_expiry, &token_pair.refresh, token_pair.refresh_expiry).await?;
+    connection.reset_refresh_state().await?;
+    audit::record(&db, event).await;
+
+    // Not being able to retrieve the Exact user should not fail the login,
+    // the `/me` endpoint will retry fetching it when it is requested
+    match get_me(&exact_client, connection.region, &token_pair.access).await {
+        Ok(me) => connection.set_exact_user(&me.into()).await?,
+        Err(e) => warn!("Failed to retrieve Exact user for connection {}: {e}", connection.id),
+    }
+
+    let success_query = serde_qs::to_string(&SuccessQuery {
+        connection: &connection.id,
+    }).unwrap();
+    Ok(Redirect::new(append_query(&auth_start.caller, &success_query)))
+}
+
+/// Append a query string to a URL, which may already have a query string and fragment
+fn append_query(url: &str, query: &str) -> String {
+    let (url, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+
+    let separator = if url.contains('?') { '&' } else { '?' };
+    match fragment {
+        Some(fragment) => format!("{url}{separator}{query}#{fragment}"),
+        None => format!("{url}{separator}{query}"),
+    }
+}
@@ -1,16 +1,16 @@
 use actix_web::web;
 use serde::{Deserialize, Serialize};
 use tracing::instrument;
-use crate::{AuthData, ConfigData, MysqlData};
-use crate::error::WebResult;
-use crate::exact_api::get_exact_url;
+use dal::{AuditAction, LoginTicket};
+use crate::{AllowedCallersData, ConfigData, DatabaseData, ExactClientData};
+use crate::audit::{self, ClientInfo};
+use crate::error::{Error, WebResult};
 use crate::routes::redirect::Redirect;
 
 #[derive(Deserialize)]
 pub struct Query {
-    bearer: String,
-    scopes: String,
-    caller: String,
+    /// Ticket created with `POST /api/v1/login-ticket`
+    ticket: String,
 }
 
 #[derive(Serialize)]
@@ -23,27 +23,37 @@ struct OAuth2Query<'a> {
     scopes: &'a str,
 }
 
-const EXACT_OAUTH2_LOGIN_URI: &str = "/api/oauth2/auth";
-const SCOPE: &str = "nl.mrfriendly.exact";
+#[instrument(skip(db, config, allowed_callers, exact_client, query, client))]
+pub async fn login(db: DatabaseData, config: ConfigData, allowed_callers: AllowedCallersData, exact_client: ExactClientData, query: web::Query<Query>, client: ClientInfo) -> WebResult<Redirect> {
+    // The ticket is consumed right away, so that it can not be replayed
+    let ticket = LoginTicket::consume(db.as_ref().clone(), &query.ticket).await?
+        .ok_or(Error::Forbidden("Unknown ticket".into()))?;
 
-#[instrument(skip(mysql, config, auth, query))]
-pub async fn login(mysql: MysqlData, config: ConfigData, auth: AuthData, query: web::Query<Query>) -> WebResult<Redirect> {
-    let auth_user = mrauth::User::get_user(&auth, &query.bearer, SCOPE).await?;
-    let user = match dal::User::get_by_id(mysql.as_ref().clone(), &auth_user.id)? {
-        Some(x) => x,
-        None => dal::User::create(mysql.as_ref().clone(), &auth_user.id)?
-    };
+    // The ticket was created by the user themselves
+    let event = client.audit_event(&ticket.user.id, Some(&ticket.user.id), None, AuditAction::LoginStarted);
+    if ticket.is_expired(config.login_ticket_ttl_sec) {
+        audit::record_failure(&db, event, "Expired ticket").await;
+        return Err(Error::Forbidden("Expired ticket".into()));
+    }
+
+    // The list of allowed callers may have changed since the ticket was created
+    if !allowed_callers.is_allowed(&ticket.caller) {
+        audit::record_failure(&db, event, format!("Caller '{}' is not allowed", ticket.caller)).await;
+        return Err(Error::BadRequest("Caller is not allowed".into()));
+    }
+
+    let auth_start = ticket.user.start_authorization(&ticket.exact_scopes, &ticket.caller, ticket.region, ticket.connection_label.as_deref(), ticket.connection_id.as_deref()).await?;
+    audit::record(&db, event).await;
 
-    let auth_start = user.start_authorization(&query.scopes, &query.caller)?;
     let query = serde_qs::to_string(&OAuth2Query {
         client_id: &config.exact_client_id,
         redirect_uri: &config.redirect_uri,
         state: &auth_start.id,
         response_type: "code",
         force_login: 1,
-        scopes: &query.scopes,
+        scopes: &ticket.exact_scopes,
     }).unwrap();
 
-    let url = format!("{}?{query}", get_exact_url(EXACT_OAUTH2_LOGIN_URI));
+    let url = format!("{}?{query}", exact_client.endpoints().auth_url(ticket.region));
     Ok(Redirect::new(url))
 }
\ No newline at end of file
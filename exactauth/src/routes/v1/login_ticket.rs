@@ -0,0 +1,66 @@
+use std::str::FromStr;
+use actix_multiresponse::Payload;
+use mrauth::actix::BearerHeader;
+use serde::Serialize;
+use tracing::instrument;
+use dal::{Connection, MAX_CONNECTION_LABEL_LEN, Region};
+use proto::{CreateLoginTicketRequest, CreateLoginTicketResponse};
+use crate::{AllowedCallersData, AuthData, ConfigData, DatabaseData};
+use crate::error::{Error, WebResult};
+
+const SCOPE: &str = "nl.mrfriendly.exact";
+
+#[derive(Serialize)]
+struct LoginQuery<'a> {
+    ticket: &'a str,
+}
+
+#[instrument(skip_all)]
+pub async fn login_ticket(db: DatabaseData, config: ConfigData, auth: AuthData, allowed_callers: AllowedCallersData, bearer: BearerHeader, Payload(request): Payload<CreateLoginTicketRequest>) -> WebResult<Payload<CreateLoginTicketResponse>> {
+    let auth_user = mrauth::User::get_user(&auth, &bearer, SCOPE).await?;
+
+    if !allowed_callers.is_allowed(&request.caller) {
+        return Err(Error::BadRequest("Caller is not allowed".into()));
+    }
+
+    let region = match &request.region {
+        Some(region) => Region::from_str(region).map_err(|e| Error::BadRequest(e.to_string()))?,
+        None => Region::default(),
+    };
+
+    if let Some(label) = &request.connection_label {
+        if label.is_empty() || label.chars().count() > MAX_CONNECTION_LABEL_LEN {
+            return Err(Error::BadRequest(format!("Connection label must be between 1 and {MAX_CONNECTION_LABEL_LEN} characters")));
+        }
+    }
+
+    // Only the user who logged in to Exact for a shared connection can reauthorize it
+    if let Some(connection_id) = &request.connection_id {
+        if request.connection_label.is_some() {
+            return Err(Error::BadRequest("Only one of connection ID and connection label may be set".into()));
+        }
+
+        let connection = Connection::get_by_id(db.as_ref().clone(), connection_id).await?
+            .ok_or(Error::NotFound)?;
+        if connection.user_id != auth_user.id || connection.owner_id.is_none() {
+            return Err(Error::NotFound);
+        }
+    }
+
+    let user = match dal::User::get_by_id(db.as_ref().clone(), &auth_user.id).await? {
+        Some(x) => x,
+        None => dal::User::create(db.as_ref().clone(), &auth_user.id).await?
+    };
+
+    let ticket = user.create_login_ticket(&request.scopes, &request.caller, region, request.connection_label.as_deref(), request.connection_id.as_deref()).await?;
+
+    let query = serde_qs::to_string(&LoginQuery {
+        ticket: &ticket.id,
+    }).unwrap();
+    let url = format!("{}/api/v1/login?{query}", config.public_url.trim_end_matches('/'));
+
+    Ok(Payload(CreateLoginTicketResponse {
+        url,
+        expires_at: ticket.timestamp + config.login_ticket_ttl_sec,
+    }))
+}
@@ -0,0 +1,34 @@
+use actix_multiresponse::Payload;
+use actix_web::web;
+use mrauth::actix::BearerHeader;
+use crate::{AuthData, DatabaseData, ExactClientData, RefresherData};
+use crate::error::{Error, WebResult};
+use crate::exact_api::get_me;
+use crate::routes::v1::connections::{ConnectionQuery, select_connection};
+use proto::GetMeResponse;
+
+const SCOPE: &str = "nl.mrfriendly.exact";
+
+pub async fn me(db: DatabaseData, auth: AuthData, refresher: RefresherData, exact_client: ExactClientData, bearer: BearerHeader, query: web::Query<ConnectionQuery>) -> WebResult<Payload<GetMeResponse>> {
+    let auth_user = mrauth::User::get_user(&auth, &bearer, SCOPE).await?;
+    let connection = select_connection(&db, &auth_user.id, query.connection.as_deref()).await?;
+
+    let exact_user = match connection.get_exact_user().await? {
+        Some(x) => x,
+        None => {
+            // Retrieving the Exact user during login failed, try again now
+            let access_token = refresher.get_valid_access_token(&connection).await?
+                .ok_or(Error::NotFound)?;
+            let exact_user = get_me(&exact_client, connection.region, &access_token.token).await?.into();
+            connection.set_exact_user(&exact_user).await?;
+            exact_user
+        }
+    };
+
+    Ok(Payload(GetMeResponse {
+        exact_user_id: exact_user.exact_user_id,
+        full_name: exact_user.full_name,
+        email: exact_user.email,
+        current_division: exact_user.current_division,
+    }))
+}
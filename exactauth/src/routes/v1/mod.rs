@@ -3,8 +3,14 @@ use actix_web::web::ServiceConfig;
 use crate::routable::Routable;
 
 mod access_token;
+mod admin;
+mod connections;
+mod exact;
 mod logged_in;
 mod login;
+mod login_ticket;
+mod me;
+mod status;
 
 pub struct Router;
 
@@ -12,8 +18,15 @@ impl Routable for Router {
     fn configure(config: &mut ServiceConfig) {
         config.service(web::scope("/v1")
             .route("/login", web::get().to(login::login))
+            .route("/login-ticket", web::post().to(login_ticket::login_ticket))
             .route("/logged-in", web::get().to(logged_in::logged_in))
             .route("/access-token", web::get().to(access_token::access_token))
+            .route("/me", web::get().to(me::me))
+            .route("/connections", web::get().to(connections::connections))
+            .route("/connection", web::delete().to(connections::delete_connection))
+            .route("/status", web::get().to(status::status))
+            .route("/exact/{tail:.*}", web::route().to(exact::exact))
+            .configure(admin::Router::configure)
         );
     }
 }
\ No newline at end of file
@@ -0,0 +1,35 @@
+use actix_multiresponse::Payload;
+use actix_web::web;
+use mrauth::actix::BearerHeader;
+use crate::{AuthData, DatabaseData};
+use crate::error::{Error, WebResult};
+use crate::routes::v1::connections::{ConnectionQuery, select_connection};
+use proto::GetStatusResponse;
+
+const SCOPE: &str = "nl.mrfriendly.exact";
+
+/// Whether the user has connected Exact, and whether the connection is still usable.
+/// Unlike `/access-token`, a missing connection is not an error
+pub async fn status(db: DatabaseData, auth: AuthData, bearer: BearerHeader, query: web::Query<ConnectionQuery>) -> WebResult<Payload<GetStatusResponse>> {
+    let auth_user = mrauth::User::get_user(&auth, &bearer, SCOPE).await?;
+    let connection = match select_connection(&db, &auth_user.id, query.connection.as_deref()).await {
+        Ok(x) => x,
+        Err(Error::NotFound) => return Ok(Payload(GetStatusResponse::default())),
+        Err(e) => return Err(e),
+    };
+
+    let access_token = connection.get_access_token().await?;
+    let refresh_token = connection.get_refresh_token().await?;
+
+    Ok(Payload(GetStatusResponse {
+        connected: true,
+        access_token_expires_at: access_token.map(|token| token.expiry),
+        refresh_token_expires_at: refresh_token.map(|token| token.expiry),
+        last_refreshed_at: connection.last_refreshed_at,
+        reauthorization_required: connection.reauthorization_required,
+        scopes: connection.requested_scopes()
+            .map(|scopes| scopes.into_iter().map(str::to_string).collect())
+            .unwrap_or_default(),
+        connection_id: Some(connection.id),
+    }))
+}
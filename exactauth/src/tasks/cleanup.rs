@@ -0,0 +1,27 @@
+use std::time::Duration;
+use actix_web::cookie::time;
+use tracing::{trace, warn};
+use dal::{AuthorizationStart, Database, LoginTicket};
+
+const JOB_INTERVAL_SEC: u64 = 300;
+
+/// Periodically delete authorization starts and login tickets which have expired,
+/// i.e. logins that were started but never completed.
+pub fn start_cleanup_task(db: Database, authorization_start_ttl_sec: i64, login_ticket_ttl_sec: i64) {
+    tokio::spawn(async move {
+        loop {
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+            match AuthorizationStart::delete_created_before(db.clone(), now - authorization_start_ttl_sec).await {
+                Ok(deleted) => trace!("Deleted {deleted} expired authorization starts"),
+                Err(e) => warn!("Failed to delete expired authorization starts: {e}"),
+            }
+
+            match LoginTicket::delete_created_before(db.clone(), now - login_ticket_ttl_sec).await {
+                Ok(deleted) => trace!("Deleted {deleted} expired login tickets"),
+                Err(e) => warn!("Failed to delete expired login tickets: {e}"),
+            }
+
+            tokio::time::sleep(Duration::from_secs(JOB_INTERVAL_SEC)).await;
+        }
+    });
+}
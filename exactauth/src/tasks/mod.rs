@@ -1 +1,2 @@
-pub mod refresh_tokens;
\ No newline at end of file
+pub mod refresh_tokens;
+pub mod cleanup;
\ No newline at end of file
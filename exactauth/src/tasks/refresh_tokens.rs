@@ -1,25 +1,34 @@
 
 use std::time::Duration;
+use futures::stream::{self, StreamExt};
 use actix_web::cookie::time;
-use thiserror::Error;
 use tracing::{trace, warn};
-use dal::{Mysql, User};
-use crate::exact_api::TokenError;
+use dal::{Connection, Database};
+use crate::refresher::{REFRESH_WINDOW_SEC, RefreshError, Refresher, RefreshOutcome};
 
-const JOB_INTERVAL_SEC: u64 = 15;
 const JOB_FAIL_INTERVAL_SEC: u64 = 5;
+/// The scheduler checks for due tokens at least this often,
+/// so that tokens obtained by new logins are picked up
+const JOB_MAX_INTERVAL_SEC: i64 = 60;
+/// Backoff after the first failed refresh of a single connection, doubled for every consecutive failure
+const USER_BACKOFF_BASE_SEC: i64 = 5;
+const USER_BACKOFF_MAX_SEC: i64 = 900;
 
-pub fn start_refresh_token_task(mysql: Mysql, client_id: &str, client_secret: &str, redirect_uri: &str) {
-    let client_id = client_id.to_string();
-    let client_secret = client_secret.to_string();
-    let redirect_uri = redirect_uri.to_string();
+/// The result of refreshing the tokens of a single connection in the task
+enum TaskOutcome {
+    /// The refresher finished refreshing the connection
+    Completed(RefreshOutcome),
+    /// Refreshing failed, it will be retried after a backoff
+    Backoff,
+}
 
+pub fn start_refresh_token_task(db: Database, refresher: Refresher, parallelism: usize) {
     tokio::spawn(async move {
         loop {
-            match refresh_tokens(mysql.clone(), &client_id, &client_secret, &redirect_uri).await {
-                Ok(_) => {
-                    trace!("All tokens that needed refreshing refreshed. Checking again in {JOB_INTERVAL_SEC} seconds");
-                    tokio::time::sleep(Duration::from_secs(JOB_INTERVAL_SEC)).await;
+            match refresh_tokens(db.clone(), &refresher, parallelism).await {
+                Ok(sleep_sec) => {
+                    trace!("All tokens that needed refreshing refreshed. Checking again in {sleep_sec} seconds");
+                    tokio::time::sleep(Duration::from_secs(sleep_sec)).await;
                 },
                 Err(e) => {
                     warn!("Failed to refresh tokens: {e}. Retrying in {JOB_FAIL_INTERVAL_SEC} seconds");
@@ -30,58 +39,165 @@ pub fn start_refresh_token_task(mysql: Mysql, client_id: &str, client_secret: &s
     });
 }
 
-#[derive(Debug, Error)]
-pub enum RefreshError {
-    #[error("DAL error: {0}")]
-    Dal(#[from] dal::Error),
-    #[error("Token error: {0}")]
-    Token(#[from] TokenError)
+/// Refresh the tokens of all connections which are due, at most `parallelism` connections at a time.
+/// Returns the number of seconds until the next token is due
+async fn refresh_tokens(db: Database, refresher: &Refresher, parallelism: usize) -> Result<u64, RefreshError> {
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let connections = Connection::list_refresh_due(db.clone(), now + REFRESH_WINDOW_SEC, now).await?;
+    trace!("Tokens of {} connections are due for refreshing", connections.len());
+
+    stream::iter(connections)
+        .for_each_concurrent(parallelism, |connection| refresh_connection_isolated(connection, refresher))
+        .await;
+
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    let sleep_sec = match Connection::next_refresh_due(db, REFRESH_WINDOW_SEC).await? {
+        Some(next_due) => (next_due - now).clamp(1, JOB_MAX_INTERVAL_SEC),
+        None => JOB_MAX_INTERVAL_SEC,
+    };
+
+    Ok(sleep_sec as u64)
 }
 
-async fn refresh_tokens(mysql: Mysql, client_id: &str, client_secret: &str, redirect_uri: &str) -> Result<(), RefreshError> {
-    let users = User::list_all(mysql)?;
-    for user in users {
-        let access_token = match user.get_access_token()? {
-            Some(x) => x,
-            None => continue,
-        };
-
-        let refresh_token = match user.get_refresh_token()? {
-            Some(x) => x,
-            None => continue,
-        };
-
-        // Check if the token is within 29 seconds of expiring
-        // Reason for 29:
-        // Exact tokens are valid for 10 minutes, but may only be refreshed after
-        // they expire within 30 seconds. The 1 second difference is to provide a buffer
-        let now = time::OffsetDateTime::now_utc();
-        let expiry = time::OffsetDateTime::from_unix_timestamp(access_token.expiry)
-            .expect("Unix timestamp was out of range");
-
-        trace!("Access token for user {} expires at {}", user.id, access_token.expiry);
-        if now > expiry || (expiry - now).whole_seconds() < 29 {
-            trace!("Access token for user {} has expired, or must be refreshed", user.id);
-
-            // Refresh the token
-            let refreshed_pair = crate::exact_api::refresh_tokens(
-                client_id,
-                client_secret,
-                redirect_uri,
-                &refresh_token.token
-            ).await?;
-
-            if refresh_token.token.ne(&refreshed_pair.refresh) {
-                user.set_refresh_token(&refreshed_pair.refresh, refreshed_pair.refresh_expiry)?;
+/// Refresh the tokens of a single connection.
+/// Failures are recorded per connection, so that a single connection can not hold up everyone else.
+/// The refresher records the outcome in the audit log
+async fn refresh_connection_isolated(mut connection: Connection, refresher: &Refresher) {
+    let outcome = match refresher.refresh(&connection).await {
+        Ok(outcome) => TaskOutcome::Completed(outcome),
+        Err(e) => {
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+            let retry_at = now + backoff_sec(connection.refresh_failures);
+            warn!("Failed to refresh tokens for connection {}: {e}. Retrying at {retry_at}", connection.id);
+            if let Err(e) = connection.record_refresh_failure(retry_at).await {
+                warn!("Failed to record refresh failure for connection {}: {e}", connection.id);
             }
 
-            user.set_access_token(&refreshed_pair.access, refreshed_pair.access_expiry)?;
+            TaskOutcome::Backoff
+        }
+    };
+
+    match outcome {
+        TaskOutcome::Completed(outcome) => trace!("Refresh outcome for connection {}: {outcome:?}", connection.id),
+        TaskOutcome::Backoff => trace!("Backing off refreshing connection {}", connection.id),
+    }
+}
+
+/// The time to wait before retrying, after `failures` consecutive failures have already occurred
+fn backoff_sec(failures: u32) -> i64 {
+    USER_BACKOFF_BASE_SEC
+        .saturating_mul(2_i64.saturating_pow(failures))
+        .min(USER_BACKOFF_MAX_SEC)
+}
+
+#[cfg(test)]
+mod tests {
+    use exact_mock::{Fault, MockExact};
+    use dal::{DatabaseConfig, LeaseHolder, Region, TokenCipher, User};
+    use crate::exact_api::exchange_code_for_token;
+    use crate::test_util::{CLIENT_ID, CLIENT_SECRET, credentials, exact_client, REDIRECT_URI};
+    use super::*;
+
+    const ENCRYPTION_KEYS: &str = "test:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+
+    struct Setup {
+        mock: MockExact,
+        db: Database,
+        refresher: Refresher,
+        connection: Connection,
+        refresh_token: String,
+    }
+
+    /// A connection which logged in to the mock, with tokens that are valid for `access_token_lifetime_sec`
+    async fn setup(access_token_lifetime_sec: i64, max_retries: u32) -> Setup {
+        let mock = MockExact::start(CLIENT_ID, CLIENT_SECRET).await.unwrap();
+        mock.set_access_token_lifetime(access_token_lifetime_sec);
 
-            trace!("Refreshed tokens for user {}", user.id);
-        } else {
-            trace!("Access token for user {} is not yet expired", user.id);
+        let exact_client = exact_client(&mock, max_retries, Duration::from_secs(5));
+
+        let token_cipher = TokenCipher::from_key_list("test", ENCRYPTION_KEYS).unwrap();
+        let db = Database::new(DatabaseConfig::Sqlite { path: ":memory:".to_string() }, token_cipher).await.unwrap();
+
+        let user = User::create(db.clone(), "user").await.unwrap();
+        let connection = user.create_connection(None, Region::Nl, "").await.unwrap();
+        let credentials = credentials();
+        let pair = exchange_code_for_token(&exact_client, &credentials, Region::Nl, &mock.issue_code(REDIRECT_URI)).await.unwrap();
+        connection.set_token_pair(&pair.access, pair.access_expiry, &pair.refresh, pair.refresh_expiry).await.unwrap();
+
+        let refresher = Refresher::new(db.clone(), LeaseHolder::new(), exact_client, credentials);
+
+        Setup {
+            mock,
+            db,
+            refresher,
+            connection,
+            refresh_token: pair.refresh,
         }
     }
 
-    Ok(())
-}
\ No newline at end of file
+    async fn reload(setup: &Setup) -> Connection {
+        Connection::get_by_id(setup.db.clone(), &setup.connection.id).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn refreshes_due_connections() {
+        let setup = setup(20, 0).await;
+
+        refresh_tokens(setup.db.clone(), &setup.refresher, 2).await.unwrap();
+
+        let connection = reload(&setup).await;
+        let refresh_token = connection.get_refresh_token().await.unwrap().unwrap();
+        assert_ne!(refresh_token.token, setup.refresh_token);
+        assert!(setup.mock.is_refresh_token_valid(&refresh_token.token));
+        assert!(!setup.mock.is_refresh_token_valid(&setup.refresh_token));
+        assert!(connection.last_refreshed_at.is_some());
+
+        setup.mock.stop().await;
+    }
+
+    #[tokio::test]
+    async fn skips_connections_not_due() {
+        let setup = setup(600, 0).await;
+
+        let sleep_sec = refresh_tokens(setup.db.clone(), &setup.refresher, 2).await.unwrap();
+        assert_eq!(sleep_sec, JOB_MAX_INTERVAL_SEC as u64);
+        // Only the code exchange
+        assert_eq!(setup.mock.token_requests(), 1);
+
+        setup.mock.stop().await;
+    }
+
+    #[tokio::test]
+    async fn invalid_grant_requires_reauthorization() {
+        let setup = setup(20, 0).await;
+        setup.mock.revoke(&setup.refresh_token);
+
+        refresh_tokens(setup.db.clone(), &setup.refresher, 2).await.unwrap();
+
+        let connection = reload(&setup).await;
+        assert!(connection.reauthorization_required);
+        assert_eq!(connection.refresh_failures, 0);
+
+        setup.mock.stop().await;
+    }
+
+    #[tokio::test]
+    async fn server_errors_back_off() {
+        let setup = setup(20, 1).await;
+        setup.mock.push_fault(Fault::ServerError(503));
+        setup.mock.push_fault(Fault::ServerError(503));
+
+        refresh_tokens(setup.db.clone(), &setup.refresher, 2).await.unwrap();
+
+        let connection = reload(&setup).await;
+        assert!(!connection.reauthorization_required);
+        assert_eq!(connection.refresh_failures, 1);
+        assert!(connection.refresh_retry_at.is_some());
+        // The first attempt and a single retry
+        assert_eq!(setup.mock.token_requests(), 3);
+        // The tokens are kept, to be refreshed after the backoff
+        assert!(setup.mock.is_refresh_token_valid(&setup.refresh_token));
+
+        setup.mock.stop().await;
+    }
+}
@@ -0,0 +1,34 @@
+//! Fixtures shared by the tests talking to the mock Exact server
+
+use std::time::Duration;
+use exact_mock::MockExact;
+use crate::exact_api::{ClientCredentials, ExactClient, ExactEndpoints, RetryPolicy};
+
+pub const CLIENT_ID: &str = "client";
+pub const CLIENT_SECRET: &str = "secret";
+pub const REDIRECT_URI: &str = "https://exactauth.example.com/api/v1/logged-in";
+
+/// A client for the mock, retrying up to `max_retries` times with short delays.
+/// `timeout` applies to both requests and responses
+pub fn exact_client(mock: &MockExact, max_retries: u32, timeout: Duration) -> ExactClient {
+    let endpoints = ExactEndpoints {
+        base_url: Some(mock.base_url()),
+        ..ExactEndpoints::default()
+    };
+    let retry_policy = RetryPolicy {
+        max_retries,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_secs(2),
+    };
+
+    ExactClient::new(endpoints, Duration::from_secs(1), timeout, timeout, retry_policy).unwrap()
+}
+
+/// The credentials the mock is started with
+pub fn credentials() -> ClientCredentials {
+    ClientCredentials {
+        client_id: CLIENT_ID.to_string(),
+        client_secret: CLIENT_SECRET.to_string(),
+        redirect_uri: REDIRECT_URI.to_string(),
+    }
+}
@@ -0,0 +1,137 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+use actix_web::HttpRequest;
+use actix_web::http::header;
+use thiserror::Error;
+
+/// The reverse proxies whose `X-Forwarded-For` entries are trusted
+#[derive(Default)]
+pub struct TrustedProxies(Vec<IpAddr>);
+
+#[derive(Debug, Error)]
+#[error("Invalid trusted proxy '{0}': not an IP address")]
+pub struct InvalidTrustedProxy(String);
+
+impl FromStr for TrustedProxies {
+    type Err = InvalidTrustedProxy;
+
+    /// Parse a comma separated list of IP addresses
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let proxies = s.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| entry.parse::<IpAddr>().map_err(|_| InvalidTrustedProxy(entry.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self(proxies))
+    }
+}
+
+impl TrustedProxies {
+    /// The IP address of the client which made the request.
+    ///
+    /// Every proxy appends the address it received the request from to `X-Forwarded-For`,
+    /// so only the entries appended by trusted proxies can be relied on. The header is walked from the right,
+    /// starting at the peer, until an address is reached which is not a trusted proxy.
+    /// If an entry is not a valid address, the last trusted proxy is the client as far as can be told
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let mut client = req.peer_addr()?.ip();
+
+        let forwarded_for = req.headers().get_all(header::X_FORWARDED_FOR)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect::<Vec<_>>();
+
+        for entry in forwarded_for.into_iter().rev() {
+            if !self.is_trusted(client) {
+                break;
+            }
+
+            client = match entry.parse::<IpAddr>() {
+                Ok(x) => x,
+                Err(_) => break,
+            };
+        }
+
+        Some(client)
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        // An IPv4 peer may be reported as an IPv4-mapped IPv6 address on dual stack sockets
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            IpAddr::V4(_) => ip,
+        };
+
+        self.0.contains(&ip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use actix_web::test::TestRequest;
+    use super::*;
+
+    fn request(peer: &str, forwarded_for: Option<&str>) -> HttpRequest {
+        let mut request = TestRequest::default()
+            .peer_addr(SocketAddr::new(peer.parse().unwrap(), 443));
+        if let Some(forwarded_for) = forwarded_for {
+            request = request.insert_header((header::X_FORWARDED_FOR, forwarded_for));
+        }
+
+        request.to_http_request()
+    }
+
+    fn ip(ip: &str) -> Option<IpAddr> {
+        Some(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn untrusted_peer_ignores_forwarded_for() {
+        let proxies = TrustedProxies::from_str("10.0.0.1").unwrap();
+        let req = request("192.0.2.10", Some("198.51.100.1"));
+
+        assert_eq!(proxies.client_ip(&req), ip("192.0.2.10"));
+    }
+
+    #[test]
+    fn trusted_proxy_chain() {
+        let proxies = TrustedProxies::from_str("10.0.0.1, 10.0.0.2").unwrap();
+        let req = request("10.0.0.1", Some("203.0.113.5, 198.51.100.1, 10.0.0.2"));
+
+        // 198.51.100.1 is not trusted, so whatever it appended may be spoofed
+        assert_eq!(proxies.client_ip(&req), ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn trusted_peer_without_forwarded_for() {
+        let proxies = TrustedProxies::from_str("10.0.0.1").unwrap();
+        let req = request("10.0.0.1", None);
+
+        assert_eq!(proxies.client_ip(&req), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn invalid_forwarded_for_entry() {
+        let proxies = TrustedProxies::from_str("10.0.0.1,10.0.0.2").unwrap();
+        let req = request("10.0.0.1", Some("198.51.100.1, unknown, 10.0.0.2"));
+
+        assert_eq!(proxies.client_ip(&req), ip("10.0.0.2"));
+    }
+
+    #[test]
+    fn invalid_trusted_proxy() {
+        assert!(TrustedProxies::from_str("10.0.0.1,proxy.example.com").is_err());
+        assert!(TrustedProxies::from_str("").unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn ipv4_mapped_peer() {
+        let proxies = TrustedProxies::from_str("10.0.0.1").unwrap();
+        let req = request("::ffff:10.0.0.1", Some("198.51.100.1"));
+
+        assert_eq!(proxies.client_ip(&req), ip("198.51.100.1"));
+    }
+}